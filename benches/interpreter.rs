@@ -0,0 +1,47 @@
+use baux2::interpreter::{run_interpreter, Value};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
+
+const ARITHMETIC_LOOP: &str = "
+WA MOE sum = 0
+PONDE i 0..100000 {
+    CO sum = <sum + i>
+}
+";
+
+const STRING_OUTPUT: &str = "
+WA KIRA line = \"BAU BAU Ruffians! Kono kyoku wa kawaiku utaou ne tte itta desho!!\"
+PONDE i 0..10000 {
+    BAU line
+    BAU \"Hear the howling of my soul!\"
+}
+";
+
+const VARIABLE_LOOKUP: &str = "
+WA MOE fuwawa = 1
+WA MOE mococo = 2
+WA MOE pero = 3
+WA MOE total = 0
+PONDE i 0..20000 {
+    CO fuwawa = <fuwawa + mococo>
+    CO mococo = <pero * 2>
+    CO total = <total + fuwawa>
+    CO pero = <mococo - pero>
+}
+";
+
+fn run(code: &str) -> String {
+    let mut variables: HashMap<String, Value> = HashMap::new();
+    let mut output = String::new();
+    run_interpreter(code, &mut variables, &mut output);
+    output
+}
+
+fn interpreter_benchmarks(c: &mut Criterion) {
+    c.bench_function("arithmetic_loop", |b| b.iter(|| run(black_box(ARITHMETIC_LOOP))));
+    c.bench_function("string_output", |b| b.iter(|| run(black_box(STRING_OUTPUT))));
+    c.bench_function("variable_lookup", |b| b.iter(|| run(black_box(VARIABLE_LOOKUP))));
+}
+
+criterion_group!(benches, interpreter_benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,12 @@
+#![no_main]
+
+use baux2::interpreter::{run_interpreter, Value};
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+
+fuzz_target!(|data: &[u8]| {
+    let code = String::from_utf8_lossy(data);
+    let mut variables: HashMap<String, Value> = HashMap::new();
+    let mut output = String::new();
+    run_interpreter(&code, &mut variables, &mut output);
+});
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+
+use crate::interpreter::{
+    is_bau_operand, is_reserved, split_expression, strip_brackets, strip_quotes,
+};
+
+/// Which variables a program reads and writes, worked out from its tokens without running it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Usage {
+    /// Variables used as values: in expressions, string interpolation, BAU, MIDASHI, NANI,
+    /// NAGERU, BAUIF conditions, PONDE IN and MADE conditions.
+    pub reads: HashSet<String>,
+    /// Variables declared with WA or SETTEI, reassigned with CO, used as PONDE counters, or
+    /// holding a TSUKAMU error.
+    pub writes: HashSet<String>,
+}
+
+/// Classifies every variable in `tokens` (from `interpreter::tokenize`) as read or written. Both
+/// branches of every statement count, whether or not the program would reach them.
+pub fn analyze(tokens: &[String]) -> Usage {
+    let mut usage = Usage::default();
+    let token = |i: usize| tokens.get(i).map(String::as_str);
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            // `WA x = 5` has no type token.
+            "WA" if token(i + 2) == Some("=") => {
+                if let Some(name) = token(i + 1) {
+                    usage.writes.insert(name.to_string());
+                }
+                if let Some(value) = token(i + 3) {
+                    read_value(value, &mut usage.reads);
+                }
+                i += 4;
+            }
+            "WA" => {
+                if let Some(name) = token(i + 2) {
+                    usage.writes.insert(name.to_string());
+                }
+                if let Some(value) = token(i + 4) {
+                    read_value(value, &mut usage.reads);
+                }
+                i += 5;
+            }
+            // `CO a = b = 5` writes every name before an `=` and reads only the last value.
+            "CO" => {
+                i += 1;
+                while let (Some(name), Some("=")) = (token(i), token(i + 1)) {
+                    usage.writes.insert(name.to_string());
+                    i += 2;
+                }
+                if let Some(value) = token(i) {
+                    read_value(value, &mut usage.reads);
+                }
+                i += 1;
+            }
+            // HYOUMODE rows have more than one operand.
+            "BAU" => {
+                i += 1;
+                if let Some(value) = token(i) {
+                    read_value(value, &mut usage.reads);
+                }
+                while token(i + 1).is_some_and(is_bau_operand) {
+                    i += 1;
+                    read_value(&tokens[i], &mut usage.reads);
+                }
+                i += 1;
+            }
+            "MIDASHI" | "NANI" | "NAGERU" => {
+                if let Some(value) = token(i + 1) {
+                    read_value(value, &mut usage.reads);
+                }
+                i += 2;
+            }
+            "BAUIF" => {
+                i += 1;
+                while token(i).is_some_and(|t| t != "{") {
+                    read_value(&tokens[i], &mut usage.reads);
+                    i += 1;
+                }
+            }
+            "MIHARU" => i += 2,
+            // `TSUKAMU problem { ... }` names the caught error; `TSUKAMU { ... }` doesn't.
+            "TSUKAMU" => {
+                if let Some(name) = token(i + 1).filter(|&t| t != "{") {
+                    usage.writes.insert(name.to_string());
+                }
+                i += 1;
+            }
+            "SETTEI" => {
+                i += 2;
+                while token(i).is_some_and(|t| t != "}") {
+                    if token(i + 1) == Some("=") {
+                        usage.writes.insert(tokens[i].clone());
+                        if let Some(value) = token(i + 2) {
+                            read_value(value, &mut usage.reads);
+                        }
+                        i += 3;
+                    } else {
+                        i += 1;
+                    }
+                }
+            }
+            t if t == "PONDE" || t.starts_with("PONDE:") => {
+                i += 1;
+                // `PONDE idx, ch IN name` splits its counters over several tokens.
+                while let Some(counters) = token(i) {
+                    usage.writes.extend(counters.split(',').filter(|name| !name.is_empty()).map(str::to_string));
+                    i += 1;
+                    if !counters.ends_with(',') {
+                        break;
+                    }
+                }
+                if token(i) == Some("IN") {
+                    if let Some(source) = token(i + 1) {
+                        read_value(source, &mut usage.reads);
+                    }
+                    i += 2;
+                } else {
+                    while token(i).is_some_and(|t| t.contains("..")) {
+                        i += 1;
+                    }
+                }
+                if token(i) == Some("MADE") {
+                    i += 1;
+                    while token(i).is_some_and(|t| t != "{") {
+                        read_value(&tokens[i], &mut usage.reads);
+                        i += 1;
+                    }
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    usage
+}
+
+fn read_value(token: &str, reads: &mut HashSet<String>) {
+    if let Some(text) = strip_quotes(token) {
+        interpolated_names(text, reads);
+    } else if let Some(expr) = strip_brackets(token) {
+        for part in split_expression(expr) {
+            match strip_quotes(part) {
+                Some(text) => interpolated_names(text, reads),
+                None => identifiers(part, reads),
+            }
+        }
+    } else {
+        identifiers(token, reads);
+    }
+}
+
+// Names inside `{...}` in a string literal. `{{` and `}}` are literal braces.
+fn interpolated_names(text: &str, reads: &mut HashSet<String>) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' if chars.peek() == Some(&c) => {
+                chars.next();
+            }
+            '{' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                reads.insert(name.trim().to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+// Every word in `text` that could be a variable name, skipping numbers and keywords.
+fn identifiers(text: &str, reads: &mut HashSet<String>) {
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let starts_like_name = word.starts_with(|c: char| c.is_alphabetic() || c == '_');
+        if starts_like_name && !is_reserved(word) {
+            reads.insert(word.to_string());
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use druid::text::{RichText, RichTextBuilder};
+use druid::Color;
+
+const ADDED_COLOR: Color = Color::rgb8(46, 125, 50);
+const REMOVED_COLOR: Color = Color::rgb8(198, 40, 40);
+
+pub enum Change<'a> {
+    Same(&'a str),
+    Added(&'a str),
+    Removed(&'a str),
+}
+
+/// Line-by-line changes from `old` to `new`, based on their longest common subsequence.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Runs usually print the same lines at the start and end, so only the middle needs comparing.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut changes: Vec<Change> = old[..prefix].iter().map(|line| Change::Same(line)).collect();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_COMPARISONS {
+        changes.extend(old_middle.iter().map(|line| Change::Removed(line)));
+        changes.extend(new_middle.iter().map(|line| Change::Added(line)));
+    } else {
+        diff_middle(old_middle, new_middle, &mut changes);
+    }
+    changes.extend(old[old.len() - suffix..].iter().map(|line| Change::Same(line)));
+    changes
+}
+
+/// How many pairs of lines a diff compares at most. Past that, the differing middle is shown as
+/// all removed and then all added, so a huge output doesn't freeze the window.
+const MAX_COMPARISONS: usize = 25_000_000;
+
+// Hirschberg's algorithm: split `old` in half, find where the longest common subsequence crosses
+// that split in `new`, and diff each side. It only keeps one row of lengths at a time.
+fn diff_middle<'a>(old: &[&'a str], new: &[&'a str], changes: &mut Vec<Change<'a>>) {
+    match old {
+        [] => changes.extend(new.iter().map(|line| Change::Added(line))),
+        _ if new.is_empty() => changes.extend(old.iter().map(|line| Change::Removed(line))),
+        [line] => match new.iter().position(|other| other == line) {
+            Some(at) => {
+                changes.extend(new[..at].iter().map(|line| Change::Added(line)));
+                changes.push(Change::Same(line));
+                changes.extend(new[at + 1..].iter().map(|line| Change::Added(line)));
+            }
+            None => {
+                changes.push(Change::Removed(line));
+                changes.extend(new.iter().map(|line| Change::Added(line)));
+            }
+        },
+        _ => {
+            let (top, bottom) = old.split_at(old.len() / 2);
+            let forward = common_lengths(top, new);
+            let reversed = |lines: &[&'a str]| lines.iter().rev().copied().collect::<Vec<_>>();
+            let backward = common_lengths(&reversed(bottom), &reversed(new));
+            let split = (0..=new.len())
+                .max_by_key(|&j| (forward[j] + backward[new.len() - j], std::cmp::Reverse(j)))
+                .unwrap_or(0);
+            diff_middle(top, &new[..split], changes);
+            diff_middle(bottom, &new[split..], changes);
+        }
+    }
+}
+
+// lengths[j] is the length of the longest common subsequence of `old` and the first j lines of `new`.
+fn common_lengths(old: &[&str], new: &[&str]) -> Vec<usize> {
+    let mut lengths = vec![0usize; new.len() + 1];
+    for line in old {
+        // The value of lengths[j - 1] from the row before this one.
+        let mut diagonal = 0;
+        for (j, other) in new.iter().enumerate() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if line == other { diagonal + 1 } else { above.max(lengths[j]) };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+/// The diff as text, with added lines in green and removed lines in red.
+pub fn render(changes: &[Change]) -> RichText {
+    let mut builder = RichTextBuilder::new();
+    for change in changes {
+        match change {
+            Change::Same(line) => {
+                builder.push(&format!("  {}\n", line));
+            }
+            Change::Added(line) => {
+                builder.push(&format!("+ {}\n", line)).text_color(ADDED_COLOR);
+            }
+            Change::Removed(line) => {
+                builder.push(&format!("- {}\n", line)).text_color(REMOVED_COLOR);
+            }
+        }
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The diff written the way `render` prints it, without the colours.
+    fn lines(changes: &[Change]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|change| match change {
+                Change::Same(line) => format!("  {}", line),
+                Change::Added(line) => format!("+ {}", line),
+                Change::Removed(line) => format!("- {}", line),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_outputs_are_all_the_same() {
+        assert_eq!(lines(&diff_lines("a\nb\nc\n", "a\nb\nc\n")), ["  a", "  b", "  c"]);
+    }
+
+    #[test]
+    fn changes_at_the_start_or_end_only() {
+        assert_eq!(lines(&diff_lines("b\nc\n", "a\nb\nc\n")), ["+ a", "  b", "  c"]);
+        assert_eq!(lines(&diff_lines("a\nb\nc\n", "b\nc\n")), ["- a", "  b", "  c"]);
+        assert_eq!(lines(&diff_lines("a\nb\n", "a\nb\nc\n")), ["  a", "  b", "+ c"]);
+        assert_eq!(lines(&diff_lines("a\nb\nc\n", "a\nb\n")), ["  a", "  b", "- c"]);
+    }
+
+    #[test]
+    fn disjoint_outputs_are_removed_then_added() {
+        assert_eq!(lines(&diff_lines("a\nb\n", "c\nd\n")), ["- a", "- b", "+ c", "+ d"]);
+    }
+
+    #[test]
+    fn interleaved_edits_keep_the_longest_common_lines() {
+        let changes = diff_lines("a\nx\nb\ny\nc\n", "a\nb\nz\nc\n");
+        assert_eq!(lines(&changes), ["  a", "- x", "  b", "- y", "+ z", "  c"]);
+    }
+
+    #[test]
+    fn an_empty_side_is_all_added_or_all_removed() {
+        assert_eq!(lines(&diff_lines("", "a\nb\n")), ["+ a", "+ b"]);
+        assert_eq!(lines(&diff_lines("a\nb\n", "")), ["- a", "- b"]);
+        assert!(diff_lines("", "").is_empty());
+    }
+
+    #[test]
+    fn a_huge_middle_is_not_compared() {
+        // 6000 * 6000 pairs is over MAX_COMPARISONS, so even the shared line is left unmatched.
+        let output = |name: &str| -> String {
+            (0..6000).map(|i| if i == 3000 { "shared\n".to_string() } else { format!("{} {}\n", name, i) }).collect()
+        };
+        let (old, new) = (output("old"), output("new"));
+        let changes = diff_lines(&old, &new);
+
+        assert_eq!(changes.len(), 12000);
+        assert!(changes[..6000].iter().all(|change| matches!(change, Change::Removed(_))));
+        assert!(changes[6000..].iter().all(|change| matches!(change, Change::Added(_))));
+    }
+}
@@ -0,0 +1,594 @@
+use druid::kurbo::BezPath;
+use druid::text::{EditableText, Selection};
+use druid::widget::{Controller, TextBox};
+use druid::{
+    theme, BoxConstraints, Color, Env, Event, EventCtx, HotKey, KbKey, LayoutCtx, LifeCycle,
+    LifeCycleCtx, PaintCtx, RenderContext, Selector, Size, SysMods, UpdateCtx, Vec2, Widget,
+};
+use std::sync::Arc;
+
+use crate::AppState;
+use baux2::interpreter::{check_syntax, SyntaxProblem, RESERVED_WORDS};
+
+/// Sent by the code box whenever its text changes, carrying the word being typed.
+pub const COMPLETION_PREFIX: Selector<String> = Selector::new("baudol.completion-prefix");
+/// Sent to the code box to replace the word being typed with a suggestion.
+pub const INSERT_COMPLETION: Selector<String> = Selector::new("baudol.insert-completion");
+/// Sent to the code box to run the selected text.
+pub const RUN_SELECTION: Selector = Selector::new("baudol.run-selection");
+/// Sent by the code box with the code that should be run.
+pub const RUN_CODE: Selector<String> = Selector::new("baudol.run-code");
+
+const MAX_COMPLETIONS: usize = 8;
+
+const INDENT: &str = "  ";
+
+const BRACE_HIGHLIGHT: Color = Color::rgba8(145, 168, 209, 140);
+
+const PROBLEM_UNDERLINE: Color = Color::rgb8(198, 40, 40);
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The start of the word that ends at `caret`. A caret past the end or inside a character is
+/// moved back to the nearest character boundary first.
+fn word_start(text: &str, caret: usize) -> usize {
+    let mut caret = caret.min(text.len());
+    while !text.is_char_boundary(caret) {
+        caret -= 1;
+    }
+    text[..caret]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word_char(*c))
+        .last()
+        .map_or(caret, |(i, _)| i)
+}
+
+/// Names declared with WA or used as a PONDE counter anywhere in the code.
+fn declared_names(code: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    for line in code.lines() {
+        let line = line.split(';').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["WA", name, "=", ..] | ["WA", _, name, ..] => names.push(name),
+            ["PONDE", name, ..] => names.push(name),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Keywords and declared names starting with `prefix`, best matches first.
+pub fn completions(code: &str, prefix: &str) -> Vec<String> {
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<String> = RESERVED_WORDS
+        .iter()
+        .copied()
+        .chain(declared_names(code))
+        .filter(|word| word.starts_with(prefix) && *word != prefix)
+        .map(String::from)
+        .collect();
+    matches.sort_by_key(|word| (word.len(), word.clone()));
+    matches.dedup();
+    matches.truncate(MAX_COMPLETIONS);
+    matches
+}
+
+/// The brace next to `caret` and the one that matches it, found by counting depth.
+/// A brace just before the caret wins over one just after it. Braces in strings and comments
+/// are skipped, as in `brace_depth`.
+fn matching_brace(text: &str, caret: usize) -> Option<(usize, usize)> {
+    let braces = code_braces(text);
+    let candidates = [caret.checked_sub(1), Some(caret)];
+
+    for at in candidates.into_iter().flatten() {
+        let Ok(index) = braces.binary_search_by_key(&at, |&(i, _)| i) else { continue; };
+        let mut depth = 0usize;
+        match braces[index].1 {
+            '{' => {
+                for &(i, brace) in &braces[index..] {
+                    if brace == '{' {
+                        depth += 1;
+                    } else {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some((at, i));
+                        }
+                    }
+                }
+            }
+            _ => {
+                for &(i, brace) in braces[..=index].iter().rev() {
+                    if brace == '}' {
+                        depth += 1;
+                    } else {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some((at, i));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The offset of every brace outside strings and comments, in order.
+fn code_braces(text: &str) -> Vec<(usize, char)> {
+    let mut braces = Vec::new();
+    let mut in_quote = false;
+    let mut in_comment = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '\n' => {
+                in_quote = false;
+                in_comment = false;
+            }
+            _ if in_comment => {}
+            ';' if !in_quote => in_comment = true,
+            '"' => in_quote = !in_quote,
+            '{' | '}' if !in_quote => braces.push((i, c)),
+            _ => {}
+        }
+    }
+    braces
+}
+
+/// How many blocks are open at `pos`, ignoring braces in strings and comments.
+fn brace_depth(text: &str, pos: usize) -> usize {
+    let mut depth = 0usize;
+    for line in text[..pos].split('\n') {
+        let mut in_quote = false;
+        for c in line.chars() {
+            match c {
+                ';' if !in_quote => break,
+                '"' => in_quote = !in_quote,
+                '{' if !in_quote => depth += 1,
+                '}' if !in_quote => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    depth
+}
+
+fn line_start(text: &str, pos: usize) -> usize {
+    text[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn line_end(text: &str, pos: usize) -> usize {
+    text[pos..].find('\n').map_or(text.len(), |i| pos + i)
+}
+
+/// The lines a selection from `start` to `end` touches. A selection ending at the start of a
+/// line, as after selecting whole lines, doesn't include that line.
+fn selected_lines(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let end = if end > start && text[..end].ends_with('\n') { end - 1 } else { end };
+    (line_start(text, start), line_end(text, end))
+}
+
+/// Comments out every line, or uncomments them all if each non-blank line already is.
+fn toggle_comments(lines: &str) -> String {
+    let commented = lines
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .all(|line| line.trim_start().starts_with(';'));
+
+    lines
+        .split('\n')
+        .map(|line| {
+            if commented {
+                let indent = line.len() - line.trim_start().len();
+                let rest = line[indent..].strip_prefix(';').unwrap_or(&line[indent..]);
+                let rest = rest.strip_prefix(' ').unwrap_or(rest);
+                format!("{}{}", &line[..indent], rest)
+            } else if line.trim().is_empty() {
+                line.to_string()
+            } else {
+                format!("; {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// A zigzag line from `x0` to `x1`, just above `y`.
+fn squiggle(x0: f64, x1: f64, y: f64) -> BezPath {
+    const STEP: f64 = 2.0;
+    let mut path = BezPath::new();
+    path.move_to((x0, y - STEP));
+    let mut x = x0;
+    let mut up = false;
+    while x < x1 {
+        x = (x + STEP).min(x1);
+        path.line_to((x, if up { y - STEP } else { y }));
+        up = !up;
+    }
+    path
+}
+
+/// The code box, with the brace pair around the cursor highlighted and syntax
+/// problems underlined as you type.
+///
+/// The highlight is drawn from the text box's own layout, so this expects the
+/// text box to be laid out at its full height inside an outer `Scroll`.
+pub struct BraceHighlight {
+    text_box: TextBox<String>,
+    problems: Vec<SyntaxProblem>,
+}
+
+impl BraceHighlight {
+    pub fn new(text_box: TextBox<String>) -> Self {
+        BraceHighlight { text_box, problems: Vec::new() }
+    }
+
+    pub fn text_box(&self) -> &TextBox<String> {
+        &self.text_box
+    }
+
+    pub fn text_box_mut(&mut self) -> &mut TextBox<String> {
+        &mut self.text_box
+    }
+}
+
+impl Widget<String> for BraceHighlight {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut String, env: &Env) {
+        self.text_box.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &String, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.problems = check_syntax(data);
+        }
+        self.text_box.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &String, data: &String, env: &Env) {
+        self.text_box.update(ctx, old_data, data, env);
+        if old_data != data {
+            self.problems = check_syntax(data);
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &String, env: &Env) -> Size {
+        self.text_box.layout(ctx, bc, data, env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &String, env: &Env) {
+        self.text_box.paint(ctx, data, env);
+
+        if !self.text_box.text().can_read() {
+            return;
+        }
+        let session = self.text_box.text().borrow();
+        let insets = env.get(theme::TEXTBOX_INSETS);
+        let offset = Vec2::new(insets.x0, insets.y0);
+
+        for problem in &self.problems {
+            let range = problem.range.start.min(data.len())..problem.range.end.min(data.len());
+            for rect in session.layout.rects_for_range(range) {
+                let rect = rect + offset;
+                ctx.stroke(squiggle(rect.x0, rect.x1, rect.y1), &PROBLEM_UNDERLINE, 1.0);
+            }
+        }
+
+        if !ctx.has_focus() {
+            return;
+        }
+        let caret = session.selection().active;
+        if let Some((brace, partner)) = matching_brace(data, caret) {
+            for at in [brace, partner] {
+                for rect in session.layout.rects_for_range(at..at + 1) {
+                    ctx.fill(rect + offset, &BRACE_HIGHLIGHT);
+                }
+            }
+        }
+    }
+}
+
+/// Editing behaviour for the code box.
+pub struct EditorController;
+
+fn selection(editor: &BraceHighlight) -> Option<Selection> {
+    let text = editor.text_box().text();
+    if text.can_write() {
+        Some(text.borrow().selection())
+    } else {
+        None
+    }
+}
+
+/// Replaces `start..end` with `new_text` and leaves the cursor after it.
+fn replace(
+    editor: &mut BraceHighlight,
+    ctx: &mut EventCtx,
+    data: &mut String,
+    start: usize,
+    end: usize,
+    new_text: &str,
+) {
+    let mut session = editor.text_box_mut().text_mut().borrow_mut();
+    let _ = session.set_selection(Selection::new(start, end));
+    let invalidation = session.insert_text(data, new_text);
+    ctx.invalidate_text_input(invalidation);
+}
+
+impl EditorController {
+    fn replace_word(
+        &self,
+        editor: &mut BraceHighlight,
+        ctx: &mut EventCtx,
+        data: &mut String,
+        word: &str,
+    ) {
+        if let Some(selection) = selection(editor) {
+            let caret = selection.active.min(data.len());
+            replace(editor, ctx, data, word_start(data, caret), caret, word);
+        }
+    }
+
+    /// Starts the new line at the depth of the block the cursor is in.
+    fn new_line(&self, editor: &mut BraceHighlight, ctx: &mut EventCtx, data: &mut String) -> bool {
+        let Some(selection) = selection(editor) else { return false; };
+        let depth = brace_depth(data, selection.min());
+        let new_text = format!("\n{}", INDENT.repeat(depth));
+        replace(editor, ctx, data, selection.min(), selection.max(), &new_text);
+        true
+    }
+
+    /// Moves a `}` typed at the start of a line back out to its block's depth.
+    fn close_brace(&self, editor: &mut BraceHighlight, ctx: &mut EventCtx, data: &mut String) -> bool {
+        let Some(selection) = selection(editor) else { return false; };
+        let start = line_start(data, selection.min());
+        if !data[start..selection.min()].trim().is_empty() {
+            return false;
+        }
+        let depth = brace_depth(data, selection.min()).saturating_sub(1);
+        let new_text = format!("{}}}", INDENT.repeat(depth));
+        replace(editor, ctx, data, start, selection.max(), &new_text);
+        true
+    }
+
+    /// Asks for the selected text to be run, or the current line if nothing is selected.
+    fn run_selection(&self, editor: &BraceHighlight, ctx: &mut EventCtx, data: &str) {
+        let Some(selection) = selection(editor) else { return; };
+        let code = if selection.is_caret() {
+            &data[line_start(data, selection.min())..line_end(data, selection.max())]
+        } else {
+            &data[selection.range()]
+        };
+        ctx.submit_command(RUN_CODE.with(code.to_string()));
+    }
+
+    /// Toggles a `;` comment on every line the selection touches, keeping them selected.
+    fn toggle_comment(&self, editor: &mut BraceHighlight, ctx: &mut EventCtx, data: &mut String) {
+        let Some(selection) = selection(editor) else { return; };
+        let (start, end) = selected_lines(data, selection.min(), selection.max());
+        let new_text = toggle_comments(&data[start..end]);
+        replace(editor, ctx, data, start, end, &new_text);
+
+        let mut session = editor.text_box_mut().text_mut().borrow_mut();
+        if let Some(invalidation) = session.set_selection(Selection::new(start, start + new_text.len())) {
+            ctx.invalidate_text_input(invalidation);
+        }
+    }
+}
+
+impl Controller<String, BraceHighlight> for EditorController {
+    fn event(
+        &mut self,
+        child: &mut BraceHighlight,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut String,
+        env: &Env,
+    ) {
+        match event {
+            Event::Command(cmd) if cmd.is(INSERT_COMPLETION) => {
+                let word = cmd.get_unchecked(INSERT_COMPLETION);
+                self.replace_word(child, ctx, data, word);
+                ctx.request_focus();
+                ctx.set_handled();
+            }
+            // Tab takes the first suggestion, if there is one.
+            Event::KeyDown(key) if key.key == KbKey::Tab && child.text_box().text().can_read() => {
+                let caret = child.text_box().text().borrow().selection().active.min(data.len());
+                let prefix = &data[word_start(data, caret)..caret];
+                match completions(data, prefix).first() {
+                    Some(word) => {
+                        let word = word.clone();
+                        self.replace_word(child, ctx, data, &word);
+                        ctx.set_handled();
+                    }
+                    None => child.event(ctx, event, data, env),
+                }
+            }
+            Event::Command(cmd) if cmd.is(RUN_SELECTION) => {
+                self.run_selection(child, ctx, data);
+                ctx.set_handled();
+            }
+            Event::KeyDown(key) if HotKey::new(SysMods::Cmd, KbKey::Enter).matches(key) => {
+                self.run_selection(child, ctx, data);
+                ctx.set_handled();
+            }
+            Event::KeyDown(key) if HotKey::new(SysMods::Cmd, "/").matches(key) => {
+                self.toggle_comment(child, ctx, data);
+                ctx.set_handled();
+            }
+            Event::KeyDown(key) if key.key == KbKey::Enter && key.mods.is_empty() => {
+                if self.new_line(child, ctx, data) {
+                    ctx.set_handled();
+                } else {
+                    child.event(ctx, event, data, env);
+                }
+            }
+            Event::KeyDown(key)
+                if key.key == KbKey::Character("}".to_string())
+                    && !(key.mods.ctrl() || key.mods.alt() || key.mods.meta()) =>
+            {
+                if self.close_brace(child, ctx, data) {
+                    ctx.set_handled();
+                } else {
+                    child.event(ctx, event, data, env);
+                }
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+
+    fn update(
+        &mut self,
+        child: &mut BraceHighlight,
+        ctx: &mut UpdateCtx,
+        old_data: &String,
+        data: &String,
+        env: &Env,
+    ) {
+        child.update(ctx, old_data, data, env);
+        let text = child.text_box().text();
+        if old_data != data && text.can_read() {
+            let caret = text.borrow().selection().active.min(data.len());
+            let prefix = data.slice(word_start(data, caret)..caret).unwrap_or_default();
+            ctx.submit_command(COMPLETION_PREFIX.with(prefix.to_string()));
+        }
+    }
+}
+
+/// Keeps `AppState::completions` in step with the word being typed.
+pub struct CompletionController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for CompletionController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.is(COMPLETION_PREFIX) => {
+                let prefix = cmd.get_unchecked(COMPLETION_PREFIX);
+                data.completions = Arc::new(completions(&data.code, prefix));
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn word_start_finds_the_word_before_the_caret() {
+        let code = "BAU total";
+        assert_eq!(word_start(code, 0), 0);
+        assert_eq!(word_start(code, code.len()), 4);
+        assert_eq!(word_start(code, 3), 0);
+        assert_eq!(word_start(code, 4), 4);
+    }
+
+    #[test]
+    fn word_start_handles_multibyte_text_and_a_caret_past_the_end() {
+        let code = "BAU \"バウ\" バウ";
+        assert_eq!(word_start(code, code.len()), code.rfind('バ').unwrap());
+        // Inside the second byte of `ウ`, the caret is moved back to the start of it.
+        let inside = code.rfind('ウ').unwrap() + 1;
+        assert_eq!(word_start(code, inside), code.rfind('バ').unwrap());
+        assert_eq!(word_start(code, code.len() + 10), code.rfind('バ').unwrap());
+        assert_eq!(word_start("", 3), 0);
+    }
+
+    #[test]
+    fn declared_names_come_from_wa_and_ponde() {
+        let code = "WA MOE total = 0\nWA count = 1\nPONDE i 1..3 {\n  ; WA MOE hidden = 2\n}\n\
+                    CO total = 5\n";
+        assert_eq!(declared_names(code), ["total", "count", "i"]);
+    }
+
+    #[test]
+    fn completions_match_keywords_and_names_shortest_first() {
+        let code = "WA MOE pondering = 1\n";
+        assert_eq!(completions(code, "PO"), ["PONDE"]);
+        assert_eq!(completions(code, "pon"), ["pondering"]);
+        assert!(completions(code, "").is_empty());
+        assert!(completions(code, "PONDE").is_empty());
+        assert_eq!(completions(code, "MO"), ["MOE", "MOCO"]);
+    }
+
+    #[test]
+    fn braces_in_strings_and_comments_are_not_matched() {
+        let code = "PONDE i 1..2 {\n  BAU \"}\" ; } {\n  WA KIRA s = <\"{\" + \"x\">\n}\n";
+        let (open, close) = (code.find('{').unwrap(), code.rfind('}').unwrap());
+        assert_eq!(matching_brace(code, open + 1), Some((open, close)));
+        assert_eq!(matching_brace(code, close), Some((close, open)));
+        assert_eq!(code_braces(code), [(open, '{'), (close, '}')]);
+
+        let quoted = code.find("\"}").unwrap() + 1;
+        assert_eq!(matching_brace(code, quoted), None);
+        let in_expression = code.find("\"{").unwrap() + 1;
+        assert_eq!(matching_brace(code, in_expression), None);
+    }
+
+    #[test]
+    fn only_semicolons_start_comments() {
+        // `#` isn't a comment in BAUx2, so the braces after it count.
+        assert_eq!(code_braces("BAU x # {\n}"), [(8, '{'), (10, '}')]);
+        assert!(code_braces("BAU x ; {\n").is_empty());
+        assert_eq!(brace_depth("BAU x ; {\n", 10), 0);
+    }
+
+    #[test]
+    fn unbalanced_braces_have_no_match() {
+        assert_eq!(matching_brace("PONDE i 1..2 {\n  BAU i\n", 14), None);
+        assert_eq!(matching_brace("BAU i\n}\n", 7), None);
+        assert_eq!(matching_brace("{ } }", 5), None);
+        assert_eq!(matching_brace("{ } }", 3), Some((2, 0)));
+    }
+
+    #[test]
+    fn depth_for_a_new_line_and_a_closing_brace() {
+        let code = "PONDE i 1..2 {\n  PONDE j 1..2 {\n    BAU \"{\"\n  }\n";
+        // Enter after each line indents the new one to the depth there.
+        assert_eq!(brace_depth(code, code.find('\n').unwrap()), 1);
+        assert_eq!(brace_depth(code, code.find("    BAU").unwrap()), 2);
+        assert_eq!(brace_depth(code, code.len()), 1);
+        // A `}` typed at the start of a line goes one level out from there.
+        let inner_close = code.rfind('}').unwrap();
+        assert_eq!(brace_depth(code, inner_close).saturating_sub(1), 1);
+        assert_eq!(brace_depth(code, code.len()).saturating_sub(1), 0);
+        assert_eq!(brace_depth("}\n}\n", 4), 0);
+    }
+
+    #[test]
+    fn toggling_twice_gives_the_lines_back() {
+        let code = "WA MOE x = 1\n\nBAU x";
+        let commented = toggle_comments(code);
+        assert_eq!(commented, "; WA MOE x = 1\n\n; BAU x");
+        assert_eq!(toggle_comments(&commented), code);
+    }
+
+    #[test]
+    fn a_mixed_selection_is_commented_out() {
+        assert_eq!(toggle_comments("; BAU a\nBAU b"), "; ; BAU a\n; BAU b");
+    }
+
+    #[test]
+    fn indented_lines_keep_their_indent_when_uncommented() {
+        assert_eq!(toggle_comments("  ; BAU a\n    ;BAU b"), "  BAU a\n    BAU b");
+        assert_eq!(toggle_comments("  BAU a"), ";   BAU a");
+    }
+
+    #[test]
+    fn a_selection_ending_at_a_line_start_leaves_that_line_alone() {
+        let code = "BAU a\nBAU b\nBAU c\n";
+        let second = code.find("BAU b").unwrap();
+        let third = code.find("BAU c").unwrap();
+        assert_eq!(selected_lines(code, 0, third), (0, third - 1));
+        assert_eq!(selected_lines(code, second, second), (second, third - 1));
+        assert_eq!(selected_lines(code, third, third), (third, code.len() - 1));
+        assert_eq!(toggle_comments(&code[..third - 1]), "; BAU a\n; BAU b");
+    }
+}
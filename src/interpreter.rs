@@ -1,66 +1,1237 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+/// A BAUx2 value. Hosts can put these into the variable map before calling `run_interpreter`
+/// to give a script its inputs, and read them back out afterwards.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Value {
+    /// A BAULEAN: FLUFFY is `true` and FUZZY is `false`.
     Bool(bool),
+    /// A KIRA.
     Str(String),
+    /// A MOE.
     Num(f64),
 }
 
-fn evaluate_arithmetic(expr: &str, variables: &HashMap<String, Value>) -> Result<f64, String> {
+impl Value {
+    /// The BAUx2 name of this value's type.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "BAULEAN",
+            Value::Str(_) => "KIRA",
+            Value::Num(_) => "MOE",
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(s)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Num(n)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            // -0.0 == 0.0, so this also prints negative zero as a plain 0.
+            Value::Num(n) if *n == 0.0 => write!(f, "0"),
+            Value::Num(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Limits and options for a single run. The default configuration has no limits and compares
+/// numbers exactly, which is what `run_interpreter` uses.
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterConfig {
+    /// Stop the program after this many executed statements.
+    pub max_steps: Option<usize>,
+    /// Stop a PONDE loop after this many iterations.
+    pub max_loop_iterations: Option<usize>,
+    /// Stop the program when it tries to declare more than this many variables.
+    pub max_variables: Option<usize>,
+    /// Numbers closer together than this count as equal in `==` and `!=` comparisons.
+    pub epsilon: f64,
+    /// Print every MOE with exactly this many decimal places in BAU output. `None` prints as few
+    /// digits as needed.
+    pub decimal_places: Option<usize>,
+    /// What `ZENYOMU` gives, such as everything read from stdin. `None` gives an empty KIRA.
+    pub input: Option<String>,
+    /// Finish the output with a table of how long each PONDE loop took.
+    pub profile: bool,
+    /// Cut the output off after this many bytes, end it with `[output truncated]`, and stop the
+    /// program.
+    pub max_output: Option<usize>,
+    /// Finish the output with details about the run, such as the deepest block nesting reached.
+    /// A program starting with CHIHUAHUA turns this on too.
+    pub verbose: bool,
+}
+
+/// Keywords and builtins, which can't be used as variable names.
+pub const RESERVED_WORDS: &[&str] = &[
+    "WA", "CO", "BAU", "PONDE", "FUWA", "MOCO", "PE", "ROPE", "RO",
+    "IN", "KIRA", "BAULEAN", "MOE", "FLUFFY", "FUZZY", "CHIHUAHUA", "JIKAN", "ARU", "MADE", "LOWER",
+    "AND", "OR", "PERCENT", "MIDASHI", "MIHARU", "NTHROOT", "LOG", "LOG10",
+    "PI", "E", "TATAMU", "NANI", "ZENYOMU", "SETTEI",
+    "ORD", "CHR", "TAMESU", "TSUKAMU", "NAGERU", "HYOUMODE", "BAUGH", "BAUIF", "BAUELSE",
+];
+
+/// Other spellings of keywords, as `(alias, keyword)`. The tokenizer swaps each alias for its
+/// keyword, so an alias works anywhere the keyword does as a statement.
+pub const KEYWORD_ALIASES: &[(&str, &str)] = &[
+    ("バウ", "BAU"),
+    ("ポンデ", "PONDE"),
+    ("見出し", "MIDASHI"),
+    ("見張る", "MIHARU"),
+    ("何", "NANI"),
+    ("設定", "SETTEI"),
+];
+
+pub(crate) fn is_reserved(name: &str) -> bool {
+    RESERVED_WORDS.contains(&name) || KEYWORD_ALIASES.iter().any(|(alias, _)| *alias == name)
+}
+
+fn canonical_keyword(token: String) -> String {
+    match KEYWORD_ALIASES.iter().find(|(alias, _)| *alias == token) {
+        Some((_, keyword)) => keyword.to_string(),
+        None => token,
+    }
+}
+
+fn check_var_name(name: &str) -> Result<(), String> {
+    if is_reserved(name) {
+        return Err(format!("[ERROR: ReservedName]: '{}' is a reserved keyword", name));
+    }
+
+    let mut chars = name.chars();
+    let valid_start = matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("[ERROR: Syntax]: invalid variable name '{}'", name));
+    }
+    Ok(())
+}
+
+fn token_at<'a>(tokens: &'a [String], pc: usize, state: &mut RunState, output: &mut String) -> Option<&'a str> {
+    let token = tokens.get(pc).map(String::as_str);
+    if token.is_none() {
+        state.report(output, "[ERROR: IncompleteStatement]: Ran out of tokens in the middle of a statement");
+    }
+    token
+}
+
+pub(crate) fn strip_quotes(token: &str) -> Option<&str> {
+    token.strip_prefix('"')?.strip_suffix('"')
+}
+
+pub(crate) fn strip_brackets(token: &str) -> Option<&str> {
+    token.strip_prefix('<')?.strip_suffix('>')
+}
+
+// Replaces `{name}` in a string literal with the variable's value. `{{` and `}}` give literal braces.
+fn interpolate(text: &str, variables: &HashMap<String, Value>) -> Result<String, String> {
+    interpolate_with(text, variables, Value::to_string)
+}
+
+fn interpolate_with(
+    text: &str,
+    variables: &HashMap<String, Value>,
+    format: impl Fn(&Value) -> String,
+) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("[ERROR: Syntax]: Unclosed '{' in string".to_string()),
+                    }
+                }
+                match variables.get(name.trim()) {
+                    Some(value) => result.push_str(&format(value)),
+                    None => {
+                        return Err(format!("[ERROR: VanishValue]: Variable couldn't be found: {}", name.trim()));
+                    }
+                }
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+// How BAU prints a value, using the configured number of decimal places for MOEs.
+fn printed(value: &Value, config: &InterpreterConfig) -> String {
+    match (value, config.decimal_places) {
+        (Value::Num(n), Some(places)) => {
+            let text = format!("{:.*}", places, n);
+            // Like Display, anything that rounds to zero prints without a minus sign.
+            match text.strip_prefix('-') {
+                Some(rest) if rest.bytes().all(|b| b == b'0' || b == b'.') => rest.to_string(),
+                _ => text,
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+pub fn evaluate_arithmetic(expr: &str, variables: &HashMap<String, Value>) -> Result<f64, String> {
     let parts: Vec<&str> = expr.trim().split_whitespace().collect();
 
     if parts.len() != 3 {
         if parts.len() == 1 {
-            return match parts[0].parse() {
-                Ok(n) => Ok(n),
-                Err(_) => Err("[ERROR: InvalidValue]: Invalid number/expression".to_string()),
-            };
+            return check_finite(evaluate_operand(parts[0], variables)?);
         }
         return Err("[ERROR: InvalidExpression]: Expecting 'value operator value'".to_string());
     }
 
     let left = evaluate_operand(parts[0], variables)?;
     let right = evaluate_operand(parts[2], variables)?;
+    apply_operator(left, parts[1], right)
+}
+
+fn apply_operator(left: f64, operator: &str, right: f64) -> Result<f64, String> {
+    let result = match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "^" => left.powf(right),
+        _ => return Err("[ERROR: InvalidOperator]: Operator is not supported".to_string()),
+    };
+    check_finite(result)
+}
+
+fn check_finite(n: f64) -> Result<f64, String> {
+    if n.is_nan() {
+        Err("[ERROR: NotANumber]: Result is not a number".to_string())
+    } else if n.is_infinite() {
+        Err("[ERROR: NumericOverflow]: Result is too large for a MOE".to_string())
+    } else {
+        Ok(n)
+    }
+}
+
+const COMPARISON_OPERATORS: &[&str] = &["==", "!=", "<=", ">=", "<", ">"];
+
+fn compare(left: f64, operator: &str, right: f64, epsilon: f64) -> bool {
+    let equal = left == right || (left - right).abs() <= epsilon;
+    match operator {
+        "==" => equal,
+        "!=" => !equal,
+        "<=" => left < right || equal,
+        ">=" => left > right || equal,
+        "<" => left < right,
+        _ => left > right,
+    }
+}
+
+// KIRA comparisons are lexicographic and case-sensitive; compare `LOWER` results to ignore case.
+fn compare_text(left: &str, operator: &str, right: &str) -> bool {
+    match operator {
+        "==" => left == right,
+        "!=" => left != right,
+        "<=" => left <= right,
+        ">=" => left >= right,
+        "<" => left < right,
+        _ => left > right,
+    }
+}
+
+fn evaluate_expression(
+    expr: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<Value, String> {
+    let parts = with_defaults(split_expression(expr), variables);
+
+    if !parts.iter().any(|part| *part == "AND" || *part == "OR") {
+        return evaluate_comparison(&parts, variables, config);
+    }
+
+    // OR binds looser than AND. Clauses are evaluated left to right and only
+    // until the result is known, so `ARU x AND x > 5` never looks up a missing x.
+    for alternative in parts.split(|part| *part == "OR") {
+        let mut holds = true;
+        for clause in alternative.split(|part| *part == "AND") {
+            if !evaluate_clause(clause, variables, config)? {
+                holds = false;
+                break;
+            }
+        }
+        if holds {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+/// Replaces every `name ?? fallback` with `name`, or with `fallback` if there is no variable called
+/// `name`. This happens before anything else, so `??` binds tighter than every other operator and
+/// `<x ?? 0 + 1>` is `(x ?? 0) + 1`. Chains like `a ?? b ?? 0` take the first one that exists.
+fn with_defaults<'a>(parts: Vec<&'a str>, variables: &HashMap<String, Value>) -> Vec<&'a str> {
+    let mut resolved: Vec<&str> = Vec::new();
+    let mut parts = parts.into_iter().peekable();
+    while let Some(part) = parts.next() {
+        match (resolved.last(), parts.peek()) {
+            (Some(&name), Some(&fallback)) if part == "??" => {
+                if check_var_name(name).is_ok() && !variables.contains_key(name) {
+                    resolved.pop();
+                    resolved.push(fallback);
+                }
+                parts.next();
+            }
+            _ => resolved.push(part),
+        }
+    }
+    resolved
+}
+
+fn evaluate_clause(
+    clause: &[&str],
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<bool, String> {
+    match evaluate_comparison(clause, variables, config)? {
+        Value::Bool(b) => Ok(b),
+        _ => Err("[ERROR: IncompatibleType]: AND and OR need a BAULEAN on each side".to_string()),
+    }
+}
+
+// Chained comparisons work like Python, not C: `0 < x < 10` means `0 < x` AND `x < 10`
+// rather than comparing the BAULEAN result of `0 < x` against 10.
+fn evaluate_comparison(
+    parts: &[&str],
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<Value, String> {
+    match parts[..] {
+        ["ARU", name] => return Ok(Value::Bool(variables.contains_key(name))),
+        ["FLUFFY"] => return Ok(Value::Bool(true)),
+        ["FUZZY"] => return Ok(Value::Bool(false)),
+        ["ZENYOMU"] => return Ok(Value::Str(config.input.clone().unwrap_or_default())),
+        [operand] => {
+            if let Some(Value::Bool(b)) = variables.get(operand) {
+                return Ok(Value::Bool(*b));
+            }
+        }
+        _ => {}
+    }
+
+    let mut segments = vec![Vec::new()];
+    let mut operators = Vec::new();
+
+    for &part in parts {
+        if COMPARISON_OPERATORS.contains(&part) {
+            operators.push(part);
+            segments.push(Vec::new());
+        } else if let Some(segment) = segments.last_mut() {
+            segment.push(part);
+        }
+    }
+
+    if operators.is_empty() {
+        return evaluate_segment(&segments[0], variables);
+    }
+
+    let values = segments
+        .iter()
+        .map(|segment| evaluate_segment(segment, variables))
+        .collect::<Result<Vec<Value>, String>>()?;
+
+    for (i, op) in operators.iter().enumerate() {
+        let holds = match (&values[i], &values[i + 1]) {
+            (Value::Num(left), Value::Num(right)) => compare(*left, op, *right, config.epsilon),
+            (Value::Str(left), Value::Str(right)) => compare_text(left, op, right),
+            _ => {
+                return Err(
+                    "[ERROR: IncompatibleType]: Comparisons need two MOEs or two KIRAs".to_string()
+                );
+            }
+        };
+        if !holds {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+/// The longest range `TATAMU` will go through, so one expression can't hang the program.
+const MAX_FOLD_VALUES: i64 = 1_000_000;
+
+fn evaluate_segment(segment: &[&str], variables: &HashMap<String, Value>) -> Result<Value, String> {
+    match segment {
+        ["LOWER", operand] => match text_operand(operand, variables) {
+            Some(text) => text.map(|t| Value::Str(t.to_lowercase())),
+            None => Err("[ERROR: IncompatibleType]: LOWER requires a KIRA".to_string()),
+        },
+        ["NTHROOT", value, n] => {
+            let value = evaluate_operand(value, variables)?;
+            let n = evaluate_operand(n, variables)?;
+            if n == 0.0 {
+                return Err("[ERROR: InvalidValue]: NTHROOT needs a non-zero root".to_string());
+            }
+            let odd = n.fract() == 0.0 && n % 2.0 != 0.0;
+            if value < 0.0 && !odd {
+                return Err("[ERROR: InvalidValue]: NTHROOT of a negative number needs an odd root".to_string());
+            }
+            // powf gives NaN for any negative base, so odd roots of negatives are taken on the magnitude.
+            let root = value.abs().powf(1.0 / n).copysign(value);
+            check_finite(root).map(Value::Num)
+        }
+        ["LOG", value] => positive_operand(value, "LOG", variables).map(|v| Value::Num(v.ln())),
+        ["LOG10", value] => positive_operand(value, "LOG10", variables).map(|v| Value::Num(v.log10())),
+        ["LOG", base, value] => {
+            let base = positive_operand(base, "LOG", variables)?;
+            let value = positive_operand(value, "LOG", variables)?;
+            if base == 1.0 {
+                return Err("[ERROR: InvalidValue]: LOG can't use a base of 1".to_string());
+            }
+            Ok(Value::Num(value.log(base)))
+        }
+        ["ORD", operand] => {
+            let text = match text_operand(operand, variables) {
+                Some(text) => text?,
+                None => return Err("[ERROR: IncompatibleType]: ORD requires a KIRA".to_string()),
+            };
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Value::Num(c as u32 as f64)),
+                _ => Err("[ERROR: InvalidValue]: ORD needs a KIRA with exactly one character".to_string()),
+            }
+        }
+        ["CHR", code] => {
+            let code = evaluate_operand(code, variables)?;
+            let c = if code.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&code) {
+                char::from_u32(code as u32)
+            } else {
+                None
+            };
+            match c {
+                Some(c) => Ok(Value::Str(c.to_string())),
+                None => Err(format!("[ERROR: InvalidValue]: {} is not a valid character code", Value::Num(code))),
+            }
+        }
+        // TATAMU is meant to fold a list, but there are no lists yet (SORT, SUM and JOIN wait on
+        // them too), so for now it folds a PONDE-style range.
+        ["TATAMU", range, operator, init] => {
+            let (start, end) = parse_range(range)?;
+            if end.saturating_sub(start) >= MAX_FOLD_VALUES {
+                return Err(format!("[ERROR: InvalidRange]: TATAMU can fold at most {} values", MAX_FOLD_VALUES));
+            }
+            let mut total = evaluate_operand(init, variables)?;
+            for i in start..=end {
+                total = apply_operator(total, operator, i as f64)?;
+            }
+            Ok(Value::Num(total))
+        }
+        ["PERCENT", ref rest @ ..] if !rest.is_empty() => {
+            let fraction = evaluate_arithmetic(&rest.join(" "), variables)?;
+            let percent = (fraction * 10000.0).round() / 100.0;
+            Ok(Value::Str(format!("{}%", Value::Num(percent))))
+        }
+        [operand] => match text_operand(operand, variables) {
+            Some(text) => text.map(Value::Str),
+            None => evaluate_arithmetic(operand, variables).map(Value::Num),
+        },
+        _ => match concatenate(segment, variables) {
+            Some(text) => text.map(Value::Str),
+            None => evaluate_arithmetic(&segment.join(" "), variables).map(Value::Num),
+        },
+    }
+}
+
+fn positive_operand(operand: &str, builtin: &str, variables: &HashMap<String, Value>) -> Result<f64, String> {
+    let n = evaluate_operand(operand, variables)?;
+    if n > 0.0 {
+        Ok(n)
+    } else {
+        Err(format!("[ERROR: InvalidValue]: {} needs a positive number", builtin))
+    }
+}
+
+pub(crate) fn split_expression(expr: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut part_start = None;
+    let mut in_quote = false;
+
+    for (i, c) in expr.char_indices() {
+        if c == '"' {
+            in_quote = !in_quote;
+        }
+        if c.is_whitespace() && !in_quote {
+            if let Some(start) = part_start.take() {
+                parts.push(&expr[start..i]);
+            }
+        } else if part_start.is_none() {
+            part_start = Some(i);
+        }
+    }
+    if let Some(start) = part_start {
+        parts.push(&expr[start..]);
+    }
+    parts
+}
+
+fn text_operand(operand: &str, variables: &HashMap<String, Value>) -> Option<Result<String, String>> {
+    match strip_quotes(operand) {
+        Some(text) => Some(interpolate(text, variables)),
+        None => match variables.get(operand) {
+            Some(Value::Str(s)) => Some(Ok(s.clone())),
+            _ => None,
+        },
+    }
+}
+
+// `+` joins KIRAs when at least one operand is a string literal or KIRA variable; other operands
+// are written the same way BAU would print them.
+fn concatenate(parts: &[&str], variables: &HashMap<String, Value>) -> Option<Result<String, String>> {
+    if parts.len() < 3 || parts.len().is_multiple_of(2) || parts.iter().skip(1).step_by(2).any(|op| *op != "+") {
+        return None;
+    }
+    if !parts.iter().step_by(2).any(|operand| text_operand(operand, variables).is_some()) {
+        return None;
+    }
+
+    let mut text = String::new();
+    for operand in parts.iter().step_by(2) {
+        match text_operand(operand, variables) {
+            Some(Ok(s)) => text.push_str(&s),
+            Some(Err(e)) => return Some(Err(e)),
+            None => match variables.get(*operand) {
+                Some(value) => text.push_str(&value.to_string()),
+                None => match evaluate_operand(operand, variables) {
+                    Ok(n) => text.push_str(&Value::Num(n).to_string()),
+                    Err(e) => return Some(Err(e)),
+                },
+            },
+        }
+    }
+    Some(Ok(text))
+}
+
+fn evaluate_text(
+    token: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<String, String> {
+    let expr = strip_brackets(token).unwrap_or(token);
+    match evaluate_expression(expr, variables, config)? {
+        Value::Str(s) => Ok(s),
+        _ => Err("[ERROR: IncompatibleType]: Expression must give a KIRA".to_string()),
+    }
+}
+
+fn evaluate_condition(
+    token: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<bool, String> {
+    let expr = strip_brackets(token).unwrap_or(token);
+    match evaluate_expression(expr, variables, config)? {
+        Value::Bool(b) => Ok(b),
+        _ => Err("[ERROR: IncompatibleType]: Expression must give a BAULEAN".to_string()),
+    }
+}
+
+fn evaluate_number(
+    token: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<f64, String> {
+    let expr = strip_brackets(token).unwrap_or(token);
+    match evaluate_expression(expr, variables, config)? {
+        Value::Num(n) => Ok(n),
+        Value::Bool(_) => Err("[ERROR: IncompatibleType]: MOE cannot hold a boolean".to_string()),
+        Value::Str(_) => Err("[ERROR: IncompatibleType]: MOE cannot hold a KIRA".to_string()),
+    }
+}
+
+fn evaluate_operand(operand: &str, variables: &HashMap<String, Value>) -> Result<f64, String> {
+    match operand {
+        s if variables.contains_key(s) => {
+            match variables.get(s) {
+                Some(Value::Num(n)) => Ok(*n),
+                Some(Value::Bool(b)) => Ok(if *b { 1.0 } else { 0.0 }),
+                _ => Err("[ERROR: InvalidValue]: Variable not found or invalid type".to_string()),
+            }
+        }
+        "FLUFFY" => Ok(1.0),
+        "FUZZY" => Ok(0.0),
+        "PI" => Ok(std::f64::consts::PI),
+        "E" => Ok(std::f64::consts::E),
+        "JIKAN" => match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => Ok(elapsed.as_secs_f64()),
+            Err(_) => Err("[ERROR: InvalidValue]: System clock is set before 1970".to_string()),
+        },
+        s =>
+            match s.parse::<f64>() {
+                // `parse` also reads `inf`, `NaN` and numbers too big for a MOE.
+                Ok(n) => check_finite(n),
+                Err(_) => Err(format!("[ERROR: InvalidValue]: '{}' is an invalid number", s)),
+            }
+    }
+}
+
+/// The type a value token would give: a variable, a literal or a `<...>` expression.
+fn type_of(token: &str, variables: &HashMap<String, Value>, config: &InterpreterConfig) -> Result<&'static str, String> {
+    if strip_quotes(token).is_some() {
+        return Ok("KIRA");
+    }
+    if let Some(expr) = strip_brackets(token) {
+        return evaluate_expression(expr, variables, config).map(|value| value.type_name());
+    }
+    match token {
+        "FLUFFY" | "FUZZY" => Ok("BAULEAN"),
+        _ => match variables.get(token) {
+            Some(value) => Ok(value.type_name()),
+            None if token.parse::<f64>().is_ok() => Ok("MOE"),
+            None => Err(format!("[ERROR: VanishValue]: Variable couldn't be found: {}", token)),
+        },
+    }
+}
+
+/// The header line MIDASHI prints for a section title.
+fn section_header(token: &str, variables: &HashMap<String, Value>) -> Result<String, String> {
+    let title = match strip_quotes(token) {
+        Some(text) => interpolate(text, variables)?,
+        None => match variables.get(token) {
+            Some(value) => value.to_string(),
+            None => return Err(format!("[ERROR: VanishValue]: Variable couldn't be found: {}", token)),
+        },
+    };
+    Ok(format!("--- {} ---", title))
+}
+
+/// Parses a `start..end` PONDE range. Both ends are included.
+fn parse_range(range: &str) -> Result<(i64, i64), String> {
+    let (range_start, range_end) = match range.split("..").collect::<Vec<&str>>()[..] {
+        [range_start, range_end] => (range_start, range_end),
+        _ => return Err("[ERROR: Syntax]: Invalid range. Expected 'startInt..endInt'".to_string()),
+    };
+
+    let start = match range_start.parse::<f64>() {
+        Ok(n) => n,
+        Err(_) => return Err("[ERROR: InvalidRange]: Start value must be an integer".to_string()),
+    };
+
+    let end = match range_end.parse::<f64>() {
+        Ok(n) => n,
+        Err(_) => return Err("[ERROR: InvalidRange]: End value must be an integer".to_string()),
+    };
+
+    // Past 2^53 an f64 counter can't hold every integer, and casting to i64 would saturate.
+    const MAX_BOUND: f64 = 9_007_199_254_740_992.0;
+    if !(start.abs() <= MAX_BOUND && end.abs() <= MAX_BOUND) {
+        return Err("[ERROR: InvalidRange]: range too large".to_string());
+    }
+
+    Ok((start as i64, end as i64))
+}
+
+/// Every combination of values from `ranges`, with the last range changing fastest.
+fn range_product(ranges: &[(i64, i64)]) -> Box<dyn Iterator<Item = Vec<Value>>> {
+    match ranges.split_first() {
+        None => Box::new(std::iter::once(Vec::new())),
+        Some((&(start, end), rest)) => {
+            let rest = rest.to_vec();
+            Box::new((start..=end).flat_map(move |i| {
+                range_product(&rest).map(move |mut values| {
+                    values.insert(0, Value::Num(i as f64));
+                    values
+                })
+            }))
+        }
+    }
+}
+
+/// The value `WA var_type name = var_value` would declare.
+fn declared_value(
+    var_type: &str,
+    var_value: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<Value, String> {
+    match var_type {
+        "KIRA" => {
+            if let Some(text) = strip_quotes(var_value) {
+                interpolate(text, variables).map(Value::Str)
+            } else if strip_brackets(var_value).is_some() {
+                evaluate_text(var_value, variables, config).map(Value::Str)
+            } else {
+                match variables.get(var_value) {
+                    Some(Value::Str(s)) => Ok(Value::Str(s.clone())),
+                    _ => Err("[ERROR: IncompatibleType]: KIRA does not support a nonstring".to_string()),
+                }
+            }
+        }
+        "BAULEAN" => match var_value {
+            "FLUFFY" => Ok(Value::Bool(true)),
+            "FUZZY" => Ok(Value::Bool(false)),
+            _ if strip_brackets(var_value).is_some() => {
+                evaluate_condition(var_value, variables, config).map(Value::Bool)
+            }
+            _ => match variables.get(var_value) {
+                Some(Value::Bool(b)) => Ok(Value::Bool(*b)),
+                _ => Err(
+                    "[ERROR: IncompatibleType]: BAULEAN requires FLUFFY/FUZZY or a declared BAULEAN-type variable"
+                        .to_string(),
+                ),
+            },
+        },
+        "MOE" => {
+            if let Some(expr) = strip_brackets(var_value) {
+                evaluate_number(expr, variables, config).map(Value::Num)
+            } else {
+                match var_value.parse::<f64>() {
+                    Ok(n) => check_finite(n).map(Value::Num),
+                    Err(_) => match variables.get(var_value) {
+                        Some(Value::Num(n)) => Ok(Value::Num(*n)),
+                        _ => Err("[ERROR: InvalidValue]: Invalid number/arithmetic expression".to_string()),
+                    },
+                }
+            }
+        }
+        _ => Err(unknown_type(var_type)),
+    }
+}
+
+/// The value of `var_value` with its type worked out from the value itself: a literal, a
+/// variable, or whatever type a `<...>` expression gives.
+fn inferred_value(
+    var_value: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<Value, String> {
+    if let Some(text) = strip_quotes(var_value) {
+        return interpolate(text, variables).map(Value::Str);
+    }
+    if let Some(expr) = strip_brackets(var_value) {
+        return evaluate_expression(expr, variables, config);
+    }
+    match var_value {
+        "FLUFFY" => Ok(Value::Bool(true)),
+        "FUZZY" => Ok(Value::Bool(false)),
+        _ => match (var_value.parse::<f64>(), variables.get(var_value)) {
+            (Ok(n), _) => check_finite(n).map(Value::Num),
+            (Err(_), Some(value)) => Ok(value.clone()),
+            (Err(_), None) => Err(format!("[ERROR: Syntax]: Can't tell what type '{}' is", var_value)),
+        },
+    }
+}
+
+/// The value `CO var_name = var_value` would give, which must match the variable's existing type.
+fn reassigned_value(
+    var_name: &str,
+    var_value: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<Value, String> {
+    check_var_name(var_name)?;
+
+    match variables.get(var_name) {
+        Some(Value::Str(_)) => {
+            if let Some(text) = strip_quotes(var_value) {
+                interpolate(text, variables).map(Value::Str)
+            } else if strip_brackets(var_value).is_some() {
+                evaluate_text(var_value, variables, config).map(Value::Str)
+            } else {
+                match variables.get(var_value) {
+                    Some(Value::Str(s)) => Ok(Value::Str(s.clone())),
+                    _ => Err("[ERROR: IncompatibleType]: CO requires matching type (KIRA)".to_string()),
+                }
+            }
+        }
+        Some(Value::Bool(_)) => match var_value {
+            "FLUFFY" => Ok(Value::Bool(true)),
+            "FUZZY" => Ok(Value::Bool(false)),
+            _ if strip_brackets(var_value).is_some() => {
+                evaluate_condition(var_value, variables, config).map(Value::Bool)
+            }
+            _ => match variables.get(var_value) {
+                Some(Value::Bool(b)) => Ok(Value::Bool(*b)),
+                _ => Err("[ERROR: IncompatibleType]: CO requires matching type (BAULEAN)".to_string()),
+            },
+        },
+        Some(Value::Num(_)) => {
+            if let Some(expr) = strip_brackets(var_value) {
+                evaluate_number(expr, variables, config).map(Value::Num)
+            } else {
+                match var_value.parse::<f64>() {
+                    Ok(n) => check_finite(n).map(Value::Num),
+                    Err(_) => match variables.get(var_value) {
+                        Some(Value::Num(n)) => Ok(Value::Num(*n)),
+                        _ => Err("[ERROR: IncompatibleType]: CO requires matching type (MOE)".to_string()),
+                    },
+                }
+            }
+        }
+        None => Err(format!("[ERROR: VanishValue]: Variable could not be found in scope: {}", var_name)),
+    }
+}
+
+/// Stores a variable, printing the new value first if MIHARU is watching it and it changed.
+fn assign(
+    variables: &mut HashMap<String, Value>,
+    watched: &HashSet<String>,
+    name: &str,
+    value: Value,
+    output: &mut String,
+) {
+    if watched.contains(name) && variables.get(name) != Some(&value) {
+        output.push_str(&format!("> {} changed to {}\n", name, value));
+    }
+    variables.insert(name.to_string(), value);
+}
+
+fn step_limit_reached(state: &mut RunState, config: &InterpreterConfig, output: &mut String) -> bool {
+    state.steps += 1;
+    match config.max_steps {
+        Some(max) if state.steps > max => {
+            state.report(output, format!("[ERROR: StepLimit]: Program stopped after {} steps", max));
+            state.halted = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn output_limit_reached(state: &mut RunState, config: &InterpreterConfig, output: &mut String) -> bool {
+    match config.max_output {
+        Some(max) if state.earlier_output + output.len() > max => {
+            let mut end = max.saturating_sub(state.earlier_output);
+            while !output.is_char_boundary(end) {
+                end -= 1;
+            }
+            output.truncate(end);
+            if !output.is_empty() && !output.ends_with('\n') {
+                output.push('\n');
+            }
+            output.push_str("[output truncated]\n");
+            state.halted = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+fn variable_limit_reached(
+    state: &mut RunState,
+    variables: &HashMap<String, Value>,
+    name: &str,
+    config: &InterpreterConfig,
+    output: &mut String,
+) -> bool {
+    match config.max_variables {
+        Some(max) if variables.len() >= max && !variables.contains_key(name) => {
+            state.report(output, format!("[ERROR: MemoryLimit]: Program stopped after declaring {} variables", max));
+            state.halted = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The index of the `}` closing a block whose contents start at `start`, counting nested blocks.
+fn block_end(tokens: &[String], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, token) in tokens.iter().enumerate().skip(start) {
+        match token.as_str() {
+            "{" => depth += 1,
+            "}" if depth == 0 => return Some(i),
+            "}" => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Evaluates the condition of `BAUIF` or `MADE`, either one `<...>` expression or BAULEAN, or a
+/// comparison written out as tokens like `x >= 5`. A `$` before a name is allowed and ignored.
+fn block_condition(
+    keyword: &str,
+    condition: &[String],
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<bool, String> {
+    let operand = |token: &str| token.strip_prefix('$').unwrap_or(token).to_string();
+    match condition {
+        [] => Err(format!("[ERROR: Syntax]: {} needs a condition", keyword)),
+        [single] => evaluate_condition(&operand(single), variables, config),
+        [left, operator @ .., right] => {
+            let operator = operator.concat();
+            if !matches!(operator.as_str(), ">" | "<" | ">=" | "<=" | "==" | "!=") {
+                return Err(format!("[ERROR: Syntax]: Unknown comparison in {}: '{}'", keyword, operator));
+            }
+            let expr = format!("{} {} {}", operand(left), operator, operand(right));
+            evaluate_condition(&expr, variables, config)
+        }
+    }
+}
+
+/// What BAU prints for one operand: an interpolated KIRA literal, FLUFFY, FUZZY or a variable.
+fn printed_operand(
+    token: &str,
+    variables: &HashMap<String, Value>,
+    config: &InterpreterConfig,
+) -> Result<String, String> {
+    if let Some(text) = strip_quotes(token) {
+        return interpolate_with(text, variables, |value| printed(value, config));
+    }
+    match token {
+        "FLUFFY" => Ok(printed(&Value::Bool(true), config)),
+        "FUZZY" => Ok(printed(&Value::Bool(false), config)),
+        _ => variables
+            .get(token)
+            .map(|value| printed(value, config))
+            .ok_or_else(|| format!("[ERROR: VanishValue]: Variable couldn't be found: {}", token)),
+    }
+}
+
+/// Whether a token after a BAU operand is another operand rather than the next statement.
+pub(crate) fn is_bau_operand(token: &str) -> bool {
+    strip_quotes(token).is_some()
+        || matches!(token, "FLUFFY" | "FUZZY")
+        || !(is_reserved(token) || token == "{" || token == "}")
+}
+
+/// Separates the cells of a HYOUMODE row until the block is done and the row is aligned.
+const CELL_SEPARATOR: char = '\u{1f}';
+
+/// Pads every cell of the rows in `text` to the widest cell in its column. Lines that aren't
+/// rows, like errors and single-operand BAUs, stay as they are.
+fn align_columns(text: &str) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in text.lines().filter(|line| line.contains(CELL_SEPARATOR)) {
+        for (column, cell) in row.split(CELL_SEPARATOR).enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(column) {
+                Some(widest) => *widest = (*widest).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    let mut aligned = String::new();
+    for line in text.lines() {
+        if line.contains(CELL_SEPARATOR) {
+            let cells: Vec<String> = line
+                .split(CELL_SEPARATOR)
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            aligned.push_str(cells.join("  ").trim_end());
+        } else {
+            aligned.push_str(line);
+        }
+        aligned.push('\n');
+    }
+    aligned
+}
+
+/// Just past the `}` of the first block at or after `pc`. PONDE uses this to skip a whole loop,
+/// body and all, when it isn't run or its header has an error.
+fn skip_block(tokens: &[String], mut pc: usize) -> usize {
+    while tokens.get(pc).is_some_and(|t| t != "{") {
+        pc += 1;
+    }
+    block_end(tokens, pc + 1).map_or(tokens.len(), |end| end + 1)
+}
+
+/// The tokens of the `{ ... }` block starting at `pc`, moving `pc` past its closing brace.
+fn block_at<'a>(
+    tokens: &'a [String],
+    pc: &mut usize,
+    keyword: &str,
+    state: &mut RunState,
+    output: &mut String,
+) -> Option<&'a [String]> {
+    if tokens.get(*pc).map(String::as_str) != Some("{") {
+        state.report(output, format!("[ERROR: Syntax]: Expected '{{' after {}", keyword));
+        return None;
+    }
+    let start = *pc + 1;
+    let Some(end) = block_end(tokens, start) else {
+        state.report(output, format!("[ERROR: Syntax]: Could not find closing '}}' for {}", keyword));
+        return None;
+    };
+    *pc = end + 1;
+    Some(&tokens[start..end])
+}
+
+/// How deeply blocks can be nested before the program is rejected without running.
+const MAX_NESTING: usize = 100;
+
+/// A problem found in a program without running it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxProblem {
+    /// Byte range of the code the problem is about.
+    pub range: Range<usize>,
+    pub message: String,
+}
+
+/// Problems in `code` found without running anything: braces and expressions that are never
+/// closed, which stop it from running at all, and WA or CO statements that can't work whatever the
+/// variables hold. BAUDOL calls this on every edit to underline them.
+pub fn check_syntax(code: &str) -> Vec<SyntaxProblem> {
+    match check_braces(code) {
+        Ok(()) => check_assignments(&spanned_tokens(code)),
+        Err(problem) => vec![problem],
+    }
+}
+
+/// Problems in the WA and CO statements of `tokens`: a missing `=`, a reserved or invalid name, or
+/// an unknown type. A statement cut off at the end is left alone, since it is probably still being
+/// typed.
+fn check_assignments(tokens: &[(String, Range<usize>)]) -> Vec<SyntaxProblem> {
+    let token = |i: usize| tokens.get(i).map(|(token, _)| token.as_str());
+    let problem = |i: usize, message: String| SyntaxProblem { range: tokens[i].1.clone(), message };
+    let mut problems = Vec::new();
+
+    for (i, (keyword, _)) in tokens.iter().enumerate() {
+        match keyword.as_str() {
+            "WA" => {
+                // `WA x = 5` leaves out the type.
+                let (var_type, name) = match token(i + 2) {
+                    Some("=") => (None, i + 1),
+                    _ => (Some(i + 1), i + 2),
+                };
+                let Some(equals) = token(name + 1) else { continue; };
+                if equals != "=" {
+                    let message = "[ERROR: Syntax]: Expected '=' after variable name".to_string();
+                    problems.push(problem(name + 1, message));
+                } else if let Err(e) = check_var_name(&tokens[name].0) {
+                    problems.push(problem(name, e));
+                } else if let Some(var_type) = var_type.filter(|&at| !is_type(&tokens[at].0)) {
+                    problems.push(problem(var_type, unknown_type(&tokens[var_type].0)));
+                }
+            }
+            "CO" => {
+                let Some(equals) = token(i + 2) else { continue; };
+                if equals != "=" {
+                    let message = "[ERROR: Syntax]: Expected '=' in a reassignment".to_string();
+                    problems.push(problem(i + 2, message));
+                    continue;
+                }
+                // `CO a = b = 5` names a target before every `=`.
+                let mut name = i + 1;
+                while token(name + 1) == Some("=") {
+                    if let Err(e) = check_var_name(&tokens[name].0) {
+                        problems.push(problem(name, e));
+                        break;
+                    }
+                    name += 2;
+                }
+            }
+            _ => {}
+        }
+    }
+    problems
+}
+
+/// Whether `word` is a type WA can declare.
+fn is_type(word: &str) -> bool {
+    matches!(word, "MOE" | "KIRA" | "BAULEAN")
+}
+
+fn unknown_type(var_type: &str) -> String {
+    format!("[ERROR: Syntax]: Unknown type: {}", var_type)
+}
+
+/// Checks that every `{` has a matching `}` and every `<...>` expression is closed before anything
+/// runs, skipping braces inside strings, `<...>` expressions and comments.
+fn check_braces(code: &str) -> Result<(), SyntaxProblem> {
+    let problem = |offset: usize, message: String| SyntaxProblem { range: offset..offset + 1, message };
+    // The offset and line number of every `{` still open, and whether it starts a BAUIF or BAUELSE block.
+    let mut open_braces = Vec::new();
+    let mut in_quote = false;
+    let mut in_arithmetic = false;
+    let mut in_comment = false;
+    let mut line_number = 1;
+    let mut previous = ' ';
+    // Where the `<...>` expression being read started.
+    let mut arithmetic_start = (0, 0);
+    // The words outside strings and expressions, to tell which keyword opened each `{`.
+    let mut words: Vec<&str> = Vec::new();
+    let mut word_start = None;
+    let mut in_condition = false;
+    let mut condition_checked = 0;
+    let mut opens_conditional = false;
+
+    for (offset, c) in code.char_indices() {
+        if c == '\n' {
+            line_number += 1;
+            in_comment = false;
+        }
+        if in_comment {
+            continue;
+        }
+        let in_word = !(c.is_whitespace() || in_quote || in_arithmetic || matches!(c, '"' | '<' | ';' | '='));
+        match (word_start, in_word) {
+            (None, true) => word_start = Some(offset),
+            (Some(start), false) => {
+                let word = &code[start..offset];
+                if matches!(word, "BAUIF" | "BAUELSE") {
+                    opens_conditional = true;
+                } else if is_block_keyword(word) {
+                    opens_conditional = false;
+                }
+                words.push(word);
+                word_start = None;
+            }
+            _ => {}
+        }
+        match c {
+            ';' if !in_quote => in_comment = true,
+            '"' => in_quote = !in_quote,
+            '<' if !(in_quote
+                || in_arithmetic
+                || (is_condition(&words, &mut in_condition, &mut condition_checked) && is_comparison(code[offset + 1..].chars().next()))) =>
+            {
+                in_arithmetic = true;
+                arithmetic_start = (offset, line_number);
+            }
+            '>' if !in_quote && in_arithmetic && closes_expression(previous, &code[offset + 1..]) => {
+                in_arithmetic = false
+            }
+            '{' if !in_quote && !in_arithmetic => {
+                open_braces.push((offset, line_number, opens_conditional));
+                opens_conditional = false;
+                if open_braces.len() > MAX_NESTING {
+                    return Err(problem(offset, format!(
+                        "[ERROR: NestingTooDeep]: blocks are nested more than {} deep on line {}",
+                        MAX_NESTING, line_number
+                    )));
+                }
+            }
+            '}' if !in_quote && !in_arithmetic => {
+                let Some(_) = open_braces.pop() else {
+                    return Err(problem(offset, format!(
+                        "[ERROR: Syntax]: unbalanced braces, unmatched '}}' on line {}",
+                        line_number
+                    )));
+                };
+            }
+            _ => {}
+        }
+        previous = c;
+    }
+
+    if in_arithmetic {
+        let (offset, line_number) = arithmetic_start;
+        return Err(problem(offset, format!(
+            "[ERROR: Syntax]: unterminated '<' expression starting on line {}",
+            line_number
+        )));
+    }
+
+    match open_braces.pop() {
+        Some((offset, _, true)) => {
+            Err(problem(offset, "[ERROR: Syntax]: Could not find closing '}' for conditional".to_string()))
+        }
+        Some((offset, line_number, false)) => Err(problem(offset, format!(
+            "[ERROR: Syntax]: unbalanced braces, unmatched '{{' on line {}",
+            line_number
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Whether a `<` followed by `next` is a comparison, as in `BAUIF x < 5` or `x <= 5`, rather than
+/// the start of a `<...>` expression. Only BAUIF and MADE conditions can have one.
+fn is_comparison(next: Option<char>) -> bool {
+    next.is_some_and(|c| c.is_whitespace() || c == '=')
+}
+
+/// Keywords that are followed by a `{ ... }` block.
+fn is_block_keyword(word: &str) -> bool {
+    matches!(word, "PONDE" | "TAMESU" | "TSUKAMU" | "HYOUMODE" | "SETTEI") || word.starts_with("PONDE:")
+}
 
-    match parts[1] {
-        "+" => Ok(left + right),
-        "-" => Ok(left - right),
-        "*" => Ok(left * right),
-        "/" => Ok(left / right),
-        "%" => Ok(left % right),
-        _ => Err("[ERROR: InvalidOperator]: Operator is not supported".to_string()),
+/// Whether `words` ends inside a BAUIF or MADE condition, between the keyword and its `{`.
+/// `checked` words have been looked at already, so each call only reads the new ones.
+fn is_condition<S: AsRef<str>>(words: &[S], in_condition: &mut bool, checked: &mut usize) -> bool {
+    for word in &words[*checked..] {
+        match word.as_ref() {
+            "BAUIF" | "MADE" => *in_condition = true,
+            "{" => *in_condition = false,
+            _ => {}
+        }
     }
+    *checked = words.len();
+    *in_condition
 }
 
-fn evaluate_operand(operand: &str, variables: &HashMap<String, Value>) -> Result<f64, String> {
-    match operand {
-        s if variables.contains_key(s) => {
-            match variables.get(s) {
-                Some(Value::Num(n)) => Ok(*n),
-                Some(Value::Bool(b)) => Ok(if *b { 1.0 } else { 0.0 }),
-                _ => Err("[ERROR: InvalidValue]: Variable not found or invalid type".to_string()),
-            }
-        }
-        "FLUFFY" => Ok(1.0),
-        "FUZZY" => Ok(0.0),
-        s =>
-            match s.parse::<f64>() {
-                Ok(n) => Ok(n),
-                Err(_) => Err(format!("[ERROR: InvalidValue]: '{}' is an invalid number", s)),
-            }
+/// Whether the `>` before `rest` ends a `<...>` expression. A `>` with a space before it is a
+/// comparison, as in `<x > 5>`, unless nothing but a comment follows it on its line, as in
+/// `<a + 2 >`.
+fn closes_expression(previous: char, rest: &str) -> bool {
+    if !previous.is_whitespace() {
+        return true;
     }
+    let line = rest.split('\n').next().unwrap_or("").trim_start();
+    line.is_empty() || line.starts_with(';')
 }
 
-pub fn run_interpreter(code: &str, variables: &mut HashMap<String, Value>, output: &mut String) {
+/// Splits a program into tokens. Comments are dropped, and string literals and `<...>` expressions
+/// each stay a single token.
+pub fn tokenize(code: &str) -> Vec<String> {
+    spanned_tokens(code).into_iter().map(|(token, _)| token).collect()
+}
+
+/// The tokens of `code`, each with the byte range of the code it came from.
+fn spanned_tokens(code: &str) -> Vec<(String, Range<usize>)> {
     let mut tokens = Vec::new();
+    let mut spans = Vec::new();
     let mut in_quote = false;
     let mut in_arithmetic = false;
     let mut current_token = String::new();
+    // Where `current_token` and the `<...>` expression being read start.
+    let mut token_start = 0;
+    let mut arithmetic_start = 0;
     let mut arithmetic_expr = String::new();
     let mut skip_line = false;
+    let mut chars = code.char_indices().peekable();
+    // Whether the tokens so far end in a BAUIF or MADE condition, and how many tokens that covers.
+    let mut in_condition = false;
+    let mut condition_checked = 0;
 
-    for c in code.chars() {
+    while let Some((offset, c)) = chars.next() {
         if skip_line {
             if c == '\n' {
                 skip_line = false;
@@ -69,544 +1240,850 @@ pub fn run_interpreter(code: &str, variables: &mut HashMap<String, Value>, outpu
         }
 
         match c {
-            ';' => {
+            ';' if !in_quote => {
+                flush_token(&mut tokens, &mut spans, &mut current_token, token_start);
                 skip_line = true;
             }
-            '<' if !in_quote => {
-                if !current_token.is_empty() {
-                    tokens.push(current_token.clone());
-                    current_token.clear();
-                }
+            '<' if !(in_quote
+                || in_arithmetic
+                || (is_condition(&tokens, &mut in_condition, &mut condition_checked)
+                    && is_comparison(chars.peek().map(|&(_, c)| c)))) =>
+            {
+                flush_token(&mut tokens, &mut spans, &mut current_token, token_start);
                 in_arithmetic = true;
+                arithmetic_start = offset;
             }
-            '>' if !in_quote && in_arithmetic => {
+            '>' if !in_quote
+                && in_arithmetic
+                && closes_expression(arithmetic_expr.chars().last().unwrap_or('<'), &code[offset + 1..]) =>
+            {
                 if !arithmetic_expr.is_empty() {
                     tokens.push(format!("<{}>", arithmetic_expr.trim()));
+                    spans.push(arithmetic_start..offset + 1);
                     arithmetic_expr.clear();
                 }
                 in_arithmetic = false;
             }
             '"' => {
                 if !in_arithmetic {
+                    if current_token.is_empty() {
+                        token_start = offset;
+                    }
                     in_quote = !in_quote;
                     current_token.push(c);
                     if !in_quote {
-                        tokens.push(current_token.clone());
-                        current_token.clear();
+                        flush_token(&mut tokens, &mut spans, &mut current_token, token_start);
                     }
                 } else {
+                    // Strings inside <...> still count as quoted, so a `>` or `;` in them
+                    // doesn't end the expression.
+                    in_quote = !in_quote;
                     arithmetic_expr.push(c);
                 }
             }
             '=' if !in_quote && !in_arithmetic => {
-                if !current_token.is_empty() {
-                    tokens.push(current_token.clone());
-                    current_token.clear();
-                }
+                flush_token(&mut tokens, &mut spans, &mut current_token, token_start);
                 tokens.push("=".to_string());
+                spans.push(offset..offset + 1);
             }
             c if c.is_whitespace() && !in_quote && !in_arithmetic => {
-                if !current_token.is_empty() {
-                    tokens.push(current_token.clone());
-                    current_token.clear();
-                }
+                flush_token(&mut tokens, &mut spans, &mut current_token, token_start);
             }
+            // Tabs and newlines in an expression are stored as spaces, so indentation style
+            // never changes its token.
+            c if in_arithmetic && c.is_whitespace() && !in_quote => arithmetic_expr.push(' '),
             c if in_arithmetic => arithmetic_expr.push(c),
-            _ => current_token.push(c),
+            _ => {
+                if current_token.is_empty() {
+                    token_start = offset;
+                }
+                current_token.push(c);
+            }
         }
     }
 
-    if !current_token.is_empty() {
-        tokens.push(current_token);
-    }
+    flush_token(&mut tokens, &mut spans, &mut current_token, token_start);
     if !arithmetic_expr.is_empty() {
         tokens.push(format!("<{}>", arithmetic_expr.trim()));
+        spans.push(arithmetic_start..code.len());
     }
+    tokens.into_iter().map(canonical_keyword).zip(spans).collect()
+}
 
-    let mut pc = 0;
-    let mut suppress_class_messages = true;
-    let mut condition_stack = Vec::new();
+// Ends the token being read, if there is one, keeping where it came from.
+fn flush_token(tokens: &mut Vec<String>, spans: &mut Vec<Range<usize>>, current: &mut String, start: usize) {
+    if !current.is_empty() {
+        spans.push(start..start + current.len());
+        tokens.push(std::mem::take(current));
+    }
+}
+
+
+pub fn run_interpreter(code: &str, variables: &mut HashMap<String, Value>, output: &mut String) {
+    run_interpreter_with_config(code, variables, output, &InterpreterConfig::default());
+}
+
+/// Runs `code` with no variables and the default config. Returns everything it printed, plus the
+/// errors it printed on their own. Text printed by BAU never counts as an error.
+pub fn run_to_string(code: &str) -> (String, Vec<String>) {
+    let mut variables = HashMap::new();
+    let mut output = String::new();
+    let errors = run(code, &mut variables, &mut output, &InterpreterConfig::default());
+    (output, errors)
+}
+
+pub fn run_interpreter_with_config(
+    code: &str,
+    variables: &mut HashMap<String, Value>,
+    output: &mut String,
+    config: &InterpreterConfig,
+) {
+    run(code, variables, output, config);
+}
+
+// Runs `code` and returns the errors it printed, leaving out the ones TAMESU caught.
+fn run(
+    code: &str,
+    variables: &mut HashMap<String, Value>,
+    output: &mut String,
+    config: &InterpreterConfig,
+) -> Vec<String> {
+    if let Err(problem) = check_braces(code) {
+        output.push_str(&format!("{}\n", problem.message));
+        return vec![problem.message];
+    }
+
+    let tokens = tokenize(code);
+    let mut start = 0;
+    let mut suppress_class_messages = !config.verbose;
 
     if tokens.get(0) == Some(&"CHIHUAHUA".to_string()) {
         suppress_class_messages = false;
-        pc += 1;
+        start += 1;
+    }
+
+    let mut state = RunState {
+        steps: 0,
+        watched: HashSet::new(),
+        loops: Vec::new(),
+        halted: false,
+        catching: false,
+        raised: None,
+        handling: Vec::new(),
+        errors: Vec::new(),
+        earlier_output: 0,
+        columns: false,
+        loop_labels: Vec::new(),
+        breaking: None,
+        depth: 0,
+        deepest: 0,
+    };
+    let started = Instant::now();
+    execute(&tokens[start..], variables, output, config, &mut state);
+
+    if config.profile {
+        output.push_str(&profile_table(&state.loops, state.deepest, started.elapsed()));
+    } else if !suppress_class_messages {
+        output.push_str(&format!("deepest nesting: {}\n", state.deepest));
     }
+    if !state.halted {
+        output_limit_reached(&mut state, config, output);
+    }
+    state.errors
+}
+
+/// What a run keeps track of across every block it executes.
+struct RunState {
+    steps: usize,
+    /// Variables MIHARU is watching.
+    watched: HashSet<String>,
+    loops: Vec<LoopProfile>,
+    /// Set when a limit stops the program, so every enclosing block stops too.
+    halted: bool,
+    /// Inside a TAMESU block, where an error is caught instead of just printed.
+    catching: bool,
+    /// The first error raised inside TAMESU and where it starts in the block's output, so the
+    /// blocks inside it stop.
+    raised: Option<(usize, String)>,
+    /// The errors being handled by the TSUKAMU blocks around the one being run, which NAGERU
+    /// can raise again as they were.
+    handling: Vec<String>,
+    /// Every error printed so far, for `run_to_string`.
+    errors: Vec<String>,
+    /// Bytes already printed to the buffers around the one being written, such as the output
+    /// before a TAMESU block.
+    earlier_output: usize,
+    /// Inside HYOUMODE, where BAU can take several operands.
+    columns: bool,
+    /// The labels of the PONDE loops around the block being run, innermost last.
+    loop_labels: Vec<Option<String>>,
+    /// Set by BAUGH, with its label if it has one, until the loop it leaves is reached.
+    breaking: Option<Option<String>>,
+    /// How many blocks the one being run is nested in, and the most there have been.
+    depth: usize,
+    deepest: usize,
+}
+
+impl RunState {
+    /// Prints an error. Inside TAMESU, the first one is also kept as the error the block raised.
+    fn report(&mut self, output: &mut String, error: impl fmt::Display) {
+        let error = error.to_string();
+        if self.catching && self.raised.is_none() {
+            self.raised = Some((output.len(), error.clone()));
+        }
+        output.push_str(&error);
+        output.push('\n');
+        self.errors.push(error);
+    }
+}
+
+/// How long one PONDE loop ran in total, over every time it was reached, for
+/// `InterpreterConfig::profile`.
+struct LoopProfile {
+    /// The loop's PONDE token. Every block is a slice of the program's tokens, so this is the
+    /// same each time the loop is reached.
+    at: *const String,
+    label: String,
+    iterations: usize,
+    elapsed: Duration,
+}
+
+fn profile_table(loops: &[LoopProfile], deepest: usize, total: Duration) -> String {
+    let millis = |duration: Duration| format!("{:.3} ms", duration.as_secs_f64() * 1000.0);
+    let mut table = String::from("--- PROFILE ---\n");
+    table.push_str(&format!("{:<20} {:>10} {:>12}\n", "loop", "iterations", "time"));
+    for profile in loops {
+        table.push_str(&format!(
+            "{:<20} {:>10} {:>12}\n",
+            profile.label, profile.iterations, millis(profile.elapsed)
+        ));
+    }
+    table.push_str(&format!("{:<20} {:>10} {:>12}\n", "total", "", millis(total)));
+    table.push_str(&format!("deepest nesting: {}\n", deepest));
+    table
+}
+
+/// A BAUIF or BAUELSE block that has started but not reached its `}` yet.
+struct Conditional {
+    /// Whether the statements in the block run.
+    runs: bool,
+    /// For a BAUIF, whether a BAUELSE right after it would run.
+    else_runs: Option<bool>,
+    /// Where the block's `}` is.
+    end: usize,
+}
+
+// Runs a block, keeping track of how deeply blocks are nested. The program itself is depth 0.
+fn execute(
+    tokens: &[String],
+    variables: &mut HashMap<String, Value>,
+    output: &mut String,
+    config: &InterpreterConfig,
+    state: &mut RunState,
+) {
+    state.deepest = state.deepest.max(state.depth);
+    state.depth += 1;
+    execute_statements(tokens, variables, output, config, state);
+    state.depth -= 1;
+}
+
+fn execute_statements(
+    tokens: &[String],
+    variables: &mut HashMap<String, Value>,
+    output: &mut String,
+    config: &InterpreterConfig,
+    state: &mut RunState,
+) {
+    let mut pc = 0;
+    let mut condition_stack: Vec<Conditional> = Vec::new();
+    // Set by the `}` of a BAUIF, for the statement right after it.
+    let mut else_runs = None;
 
     while pc < tokens.len() {
-        let should_execute = condition_stack.last().copied().unwrap_or(true);
+        if output_limit_reached(state, config, output)
+            || state.raised.is_some()
+            || step_limit_reached(state, config, output)
+        {
+            return;
+        }
+        let should_execute = condition_stack.last().is_none_or(|block| block.runs);
+        let else_after_if = else_runs.take();
 
         match tokens.get(pc).map(String::as_str) {
-            Some("WA") if pc + 4 < tokens.len() => {
+            Some("WA") => {
+                pc += 1;
                 if should_execute {
-                    pc += 1;
-                    let var_type = &tokens[pc];
-                    pc += 1;
-                    let var_name = &tokens[pc];
+                    // `WA x = 5` leaves out the type, which is then inferred from the value.
+                    let var_type = match tokens.get(pc + 1) {
+                        Some(next) if next == "=" => None,
+                        _ => {
+                            let Some(var_type) = token_at(tokens, pc, state, output) else { continue; };
+                            pc += 1;
+                            Some(var_type)
+                        }
+                    };
+                    let Some(var_name) = token_at(tokens, pc, state, output) else { continue; };
                     pc += 1;
 
-                    if tokens[pc] != "=" {
-                        output.push_str("[ERROR: Syntax]: Expected '=' after variable name\n");
-                        break;
+                    let Some(equals) = token_at(tokens, pc, state, output) else { continue; };
+                    if equals != "=" {
+                        state.report(output, "[ERROR: Syntax]: Expected '=' after variable name");
+                        continue;
                     }
                     pc += 1;
 
-                    let value = match var_type.as_str() {
-                        "KIRA" => {
-                            let var_value = &tokens[pc];
-                            if var_value.starts_with('"') && var_value.ends_with('"') {
-                                Value::Str(var_value[1..var_value.len() - 1].to_string())
-                            } else {
-                                match variables.get(var_value) {
-                                    Some(Value::Str(s)) => Value::Str(s.clone()),
-                                    _ => {
-                                        output.push_str(
-                                            "[ERROR: IncompatibleType]: KIRA does not support a nonstring\n"
-                                        );
-                                        continue;
-                                    }
-                                }
-                            }
-                        }
-                        "BAULEAN" => {
-                            let var_value = &tokens[pc];
-                            match var_value.as_str() {
-                                "FLUFFY" => Value::Bool(true),
-                                "FUZZY" => Value::Bool(false),
-                                _ => {
-                                    match variables.get(var_value) {
-                                        Some(Value::Bool(b)) => Value::Bool(*b),
-                                        _ => {
-                                            output.push_str(
-                                                "[ERROR: IncompatibleType]: BAULEAN requires FLUFFY/FUZZY or a declared BAULEAN-type variable\n"
-                                            );
-                                            continue;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        "MOE" => {
-                            let var_value = &tokens[pc];
-
-                            if var_value.starts_with('<') && var_value.ends_with('>') {
-                                let expr = &var_value[1..var_value.len() - 1];
-                                match evaluate_arithmetic(expr, &variables) {
-                                    Ok(n) => Value::Num(n),
-                                    Err(e) => {
-                                        output.push_str(&format!("{}\n", e));
-                                        continue;
-                                    }
-                                }
-                            } else {
-                                match var_value.parse::<f64>() {
-                                    Ok(n) => Value::Num(n),
-                                    Err(_) => {
-                                        match variables.get(var_value) {
-                                            Some(Value::Num(n)) => Value::Num(*n),
-                                            _ => {
-                                                output.push_str(
-                                                    "[ERROR: InvalidValue]: Invalid number/arithmetic expression\n"
-                                                );
-                                                continue;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => {
-                            output.push_str(&format!("Unknown type: {}\n", var_type));
+                    // Step past the value before using it, so a bad value is never read as the next statement.
+                    let Some(var_value) = token_at(tokens, pc, state, output) else { continue; };
+                    pc += 1;
+
+                    let value = check_var_name(var_name).and_then(|()| match var_type {
+                        Some(var_type) => declared_value(var_type, var_value, variables, config),
+                        None => inferred_value(var_value, variables, config),
+                    });
+                    let value = match value {
+                        Ok(value) => value,
+                        Err(e) => {
+                            state.report(output, e);
                             continue;
                         }
                     };
 
-                    variables.insert(var_name.to_string(), value);
+                    if variable_limit_reached(state, variables, var_name, config, output) {
+                        return;
+                    }
+                    assign(variables, &state.watched, var_name, value, output);
                 }
-                pc += 1;
             }
 
-            Some("CO") if pc + 3 < tokens.len() => {
+            Some("CO") => {
                 if should_execute {
                     pc += 1;
-                    let var_name = &tokens[pc];
+                    let Some(var_name) = token_at(tokens, pc, state, output) else { continue; };
                     pc += 1;
 
-                    if tokens[pc] != "=" {
-                        output.push_str("[ERROR: Syntax]: Expected '=' in reassingment\n");
-                        break;
+                    let Some(equals) = token_at(tokens, pc, state, output) else { continue; };
+                    if equals != "=" {
+                        state.report(output, "[ERROR: Syntax]: Expected '=' in a reassignment");
+                        continue;
                     }
                     pc += 1;
 
-                    let existing_type = match variables.get(var_name) {
-                        Some(Value::Str(_)) => "KIRA",
-                        Some(Value::Bool(_)) => "BAULEAN",
-                        Some(Value::Num(_)) => "MOE",
-                        None => {
-                            output.push_str(
-                                &format!("[ERROR: VanishValue]: Variable could not be found in scope: {}\n", var_name)
-                            );
-                            continue;
+                    // `CO a = b = 5` sets every name before the last `=`.
+                    let mut targets = vec![var_name];
+                    while tokens.get(pc + 1).is_some_and(|t| t == "=") {
+                        targets.push(&tokens[pc]);
+                        pc += 2;
+                    }
+                    let Some(var_value) = token_at(tokens, pc, state, output) else { continue; };
+
+                    for var_name in targets.into_iter().rev() {
+                        match reassigned_value(var_name, var_value, variables, config) {
+                            Ok(value) => assign(variables, &state.watched, var_name, value, output),
+                            Err(e) => state.report(output, e),
                         }
-                    };
+                    }
+                }
+                pc += 1;
+            }
 
-                    let value = match existing_type {
-                        "KIRA" => {
-                            let var_value = &tokens[pc];
-                            if var_value.starts_with('"') && var_value.ends_with('"') {
-                                Value::Str(var_value[1..var_value.len() - 1].to_string())
-                            } else {
-                                match variables.get(var_value) {
-                                    Some(Value::Str(s)) => Value::Str(s.clone()),
-                                    _ => {
-                                        output.push_str(
-                                            "[ERROR: IncompatibleType]: CO requires matching type (KIRA)\n"
-                                        );
-                                        continue;
-                                    }
-                                }
-                            }
+            Some("BAU") => {
+                pc += 1;
+                if should_execute {
+                    let Some(token) = token_at(tokens, pc, state, output) else { continue; };
+                    let mut cells = vec![printed_operand(token, variables, config)];
+                    // In HYOUMODE, `BAU name score` prints one row with a cell per operand.
+                    while state.columns && tokens.get(pc + 1).is_some_and(|t| is_bau_operand(t)) {
+                        pc += 1;
+                        cells.push(printed_operand(&tokens[pc], variables, config));
+                    }
+                    match cells.into_iter().collect::<Result<Vec<_>, _>>() {
+                        Ok(cells) => {
+                            output.push_str(&format!("{}\n", cells.join(&CELL_SEPARATOR.to_string())))
                         }
-                        "BAULEAN" => {
-                            let var_value = &tokens[pc];
-                            match var_value.as_str() {
-                                "FLUFFY" => Value::Bool(true),
-                                "FUZZY" => Value::Bool(false),
-                                _ => {
-                                    match variables.get(var_value) {
-                                        Some(Value::Bool(b)) => Value::Bool(*b),
-                                        _ => {
-                                            output.push_str(
-                                                "[ERROR: IncompatibleType]: CO requires matching type (BAULEAN)\n"
-                                            );
-                                            continue;
-                                        }
-                                    }
-                                }
-                            }
+                        Err(e) => state.report(output, e),
+                    }
+                }
+                pc += 1;
+            }
+
+            Some("HYOUMODE") => {
+                pc += 1;
+                let Some(body) = block_at(tokens, &mut pc, "HYOUMODE", state, output) else { continue; };
+                if !should_execute {
+                    continue;
+                }
+                let table_start = output.len();
+                let outer_columns = std::mem::replace(&mut state.columns, true);
+                execute(body, variables, output, config, state);
+                state.columns = outer_columns;
+
+                // The table ends at an error TAMESU caught, which stays where it is below the table.
+                let table_end = state.raised.as_ref().map_or(output.len(), |&(start, _)| start);
+                let aligned = align_columns(&output[table_start..table_end]);
+                output.replace_range(table_start..table_end, &aligned);
+                if let Some((start, _)) = &mut state.raised {
+                    *start = table_start + aligned.len();
+                }
+                if state.halted || state.raised.is_some() || state.breaking.is_some() {
+                    return;
+                }
+            }
+
+            Some("NAGERU") => {
+                pc += 1;
+                if should_execute {
+                    let Some(token) = token_at(tokens, pc, state, output) else { continue; };
+                    match printed_operand(token, variables, config) {
+                        // Re-raising the error TSUKAMU caught keeps it as it was.
+                        Ok(message) if state.handling.contains(&message) => state.report(output, message),
+                        Ok(message) => state.report(output, format!("[ERROR: UserError]: {}", message)),
+                        Err(e) => {
+                            state.report(output, e);
+                            pc += 1;
+                            continue;
                         }
-                        "MOE" => {
-                            let var_value = &tokens[pc];
-                            if var_value.starts_with('<') && var_value.ends_with('>') {
-                                let expr = &var_value[1..var_value.len() - 1];
-                                match evaluate_arithmetic(expr, &variables) {
-                                    Ok(n) => Value::Num(n),
-                                    Err(e) => {
-                                        output.push_str(&format!("{}\n", e));
-                                        continue;
-                                    }
-                                }
-                            } else {
-                                match var_value.parse::<f64>() {
-                                    Ok(n) => Value::Num(n),
-                                    Err(_) => {
-                                        match variables.get(var_value) {
-                                            Some(Value::Num(n)) => Value::Num(*n),
-                                            _ => {
-                                                output.push_str(
-                                                    "[ERROR: IncompatibleType]: CO requires matching type (MOE)\n"
-                                                );
-                                                continue;
-                                            }
-                                        }
-                                    }
+                    }
+                    // Outside TAMESU, nothing can catch the error, so the program stops here.
+                    if !state.catching {
+                        state.halted = true;
+                        return;
+                    }
+                }
+                pc += 1;
+            }
+
+            Some("MIDASHI") => {
+                pc += 1;
+                if should_execute {
+                    let Some(token) = token_at(tokens, pc, state, output) else { continue; };
+                    match section_header(token, variables) {
+                        Ok(header) => output.push_str(&format!("{}\n", header)),
+                        Err(e) => state.report(output, e),
+                    }
+                }
+                pc += 1;
+            }
+
+            Some("SETTEI") => {
+                pc += 1;
+                let Some(open_brace) = token_at(tokens, pc, state, output) else { continue; };
+                if open_brace != "{" {
+                    state.report(output, "[ERROR: Syntax]: Expected '{' after SETTEI");
+                    continue;
+                }
+                pc += 1;
+
+                // Each setting is `name = value`, declared with the type of its value.
+                while tokens.get(pc).is_some_and(|t| t != "}") {
+                    let var_name = tokens[pc].as_str();
+                    let var_value = match (tokens.get(pc + 1), tokens.get(pc + 2)) {
+                        (Some(equals), Some(value)) if equals == "=" && value != "}" => value.as_str(),
+                        _ => {
+                            state.report(output, format!("[ERROR: Syntax]: Expected 'name = value' in SETTEI, found '{}'", var_name));
+                            pc += 1;
+                            continue;
+                        }
+                    };
+                    pc += 3;
+
+                    if should_execute {
+                        let value = check_var_name(var_name)
+                            .and_then(|()| inferred_value(var_value, variables, config));
+                        match value {
+                            Ok(value) => {
+                                if variable_limit_reached(state, variables, var_name, config, output) {
+                                    return;
                                 }
+                                assign(variables, &state.watched, var_name, value, output);
                             }
+                            Err(e) => state.report(output, e),
                         }
-                        _ => unreachable!(),
-                    };
+                    }
+                }
+                pc += 1;
+            }
 
-                    variables.insert(var_name.to_string(), value);
+            Some("NANI") => {
+                pc += 1;
+                if should_execute {
+                    let Some(token) = token_at(tokens, pc, state, output) else { continue; };
+                    match type_of(token, variables, config) {
+                        Ok(type_name) => output.push_str(&format!("{}\n", type_name)),
+                        Err(e) => state.report(output, e),
+                    }
                 }
                 pc += 1;
             }
 
-            Some("BAU") if pc + 1 < tokens.len() => {
+            Some("MIHARU") => {
                 pc += 1;
                 if should_execute {
-                    let token = &tokens[pc];
-                    if token.starts_with('"') && token.ends_with('"') {
-                        output.push_str(&format!("{}\n", &token[1..token.len() - 1]));
-                    } else {
-                        match variables.get(token) {
-                            Some(Value::Str(s)) => output.push_str(&format!("{}\n", s)),
-                            Some(Value::Bool(b)) => output.push_str(&format!("{}\n", b)),
-                            Some(Value::Num(n)) => output.push_str(&format!("{}\n", n)),
-                            None =>
-                                output.push_str(
-                                    &format!("[ERROR: VanishValue]: Variable couldn't be found: {}\n", token)
-                                ),
+                    let Some(var_name) = token_at(tokens, pc, state, output) else { continue; };
+                    match check_var_name(var_name) {
+                        Ok(()) => {
+                            state.watched.insert(var_name.to_string());
+                        }
+                        Err(e) => state.report(output, e),
+                    }
+                }
+                pc += 1;
+            }
+
+            Some("TAMESU") => {
+                pc += 1;
+                let Some(attempt) = block_at(tokens, &mut pc, "TAMESU", state, output) else { continue; };
+
+                // `TSUKAMU` can name a KIRA to hold the error: `TSUKAMU problem { ... }`.
+                let mut handler = None;
+                if tokens.get(pc).is_some_and(|t| t == "TSUKAMU") {
+                    pc += 1;
+                    let error_name = match tokens.get(pc) {
+                        Some(name) if name != "{" => {
+                            pc += 1;
+                            Some(name.as_str())
+                        }
+                        _ => None,
+                    };
+                    let Some(body) = block_at(tokens, &mut pc, "TSUKAMU", state, output) else { continue; };
+                    handler = Some((error_name, body));
+                }
+                if !should_execute {
+                    continue;
+                }
+
+                let outer_catching = std::mem::replace(&mut state.catching, true);
+                state.earlier_output += output.len();
+                let mut attempt_output = String::new();
+                let errors_before = state.errors.len();
+                execute(attempt, variables, &mut attempt_output, config, state);
+                let raised = state.raised.take();
+                state.catching = outer_catching;
+                state.earlier_output -= output.len();
+
+                // Limits stop the whole program and can't be caught.
+                if state.halted || state.breaking.is_some() {
+                    output.push_str(&attempt_output);
+                    return;
+                }
+                let Some((error_start, error)) = raised else {
+                    output.push_str(&attempt_output);
+                    continue;
+                };
+
+                // Keep what was printed before the error, and hand the error to TSUKAMU.
+                output.push_str(&attempt_output[..error_start]);
+                state.errors.truncate(errors_before);
+
+                if let Some((error_name, body)) = handler {
+                    if let Some(name) = error_name {
+                        if let Err(e) = check_var_name(name) {
+                            state.report(output, e);
+                            continue;
+                        }
+                        if variable_limit_reached(state, variables, name, config, output) {
+                            return;
                         }
+                        assign(variables, &state.watched, name, Value::Str(error.clone()), output);
                     }
+                    state.handling.push(error);
+                    execute(body, variables, output, config, state);
+                    state.handling.pop();
+                    if state.halted || state.raised.is_some() || state.breaking.is_some() {
+                        return;
+                    }
+                }
+            }
+
+            Some("TSUKAMU") => {
+                if should_execute {
+                    state.report(output, "[ERROR: Syntax]: TSUKAMU needs a TAMESU block right before it");
                 }
                 pc += 1;
+                if tokens.get(pc).is_some_and(|t| t != "{") {
+                    pc += 1;
+                }
+                if block_at(tokens, &mut pc, "TSUKAMU", state, output).is_none() {
+                    continue;
+                }
             }
 
-            Some("PONDE") if pc + 3 < tokens.len() => {
+            Some(keyword) if keyword == "PONDE" || keyword.starts_with("PONDE:") => {
                 if should_execute {
+                    let loop_token: *const String = &tokens[pc];
+                    // `PONDE:outer i 0..3` names the loop so `BAUGH outer` can leave it.
+                    let label = keyword.strip_prefix("PONDE:");
+                    if let Some(Err(e)) = label.map(check_var_name) {
+                        state.report(output, e);
+                        pc = skip_block(tokens, pc);
+                        continue;
+                    }
                     pc += 1;
-                    let var_name = &tokens[pc];
+                    let Some(first) = token_at(tokens, pc, state, output) else {
+                        pc = skip_block(tokens, pc);
+                        continue;
+                    };
                     pc += 1;
+                    // `PONDE idx, ch IN name` writes the counters with a space after the comma.
+                    let mut counters = first.to_string();
+                    while counters.ends_with(',') {
+                        let Some(next) = tokens.get(pc) else { break; };
+                        counters.push_str(next);
+                        pc += 1;
+                    }
+                    let counters = counters.as_str();
 
-                    let range = tokens[pc].split("..").collect::<Vec<&str>>();
-                    if range.len() != 2 {
-                        output.push_str(
-                            "[ERROR: Syntax]: Invalid range. Expected 'startInt..endInt'\n"
-                        );
+                    // `PONDE i,j 0..3 0..3` goes through every pair, with the last counter changing fastest.
+                    let var_names: Vec<&str> = counters.split(',').collect();
+                    if let Some(e) = var_names.iter().find_map(|name| check_var_name(name).err()) {
+                        state.report(output, e);
+                        pc = skip_block(tokens, pc);
                         continue;
                     }
 
-                    let start = match range[0].parse::<f64>() {
-                        Ok(n) => n,
-                        Err(_) => {
-                            output.push_str(
-                                "[ERROR: InvalidRange]: Start value must be an integer\n"
-                            );
+                    let Some(range) = token_at(tokens, pc, state, output) else {
+                        pc = skip_block(tokens, pc);
+                        continue;
+                    };
+                    let loop_values: Box<dyn Iterator<Item = Vec<Value>>> = if range == "IN" {
+                        if var_names.len() > 2 {
+                            state.report(output, "[ERROR: Syntax]: PONDE IN takes a counter, or an index and a counter");
+                            pc = skip_block(tokens, pc);
                             continue;
                         }
-                    };
-
-                    let end = match range[1].parse::<f64>() {
-                        Ok(n) => n,
-                        Err(_) => {
-                            output.push_str(
-                                "[ERROR: InvalidRange]: End value must be an integer\n"
-                            );
+                        let with_index = var_names.len() == 2;
+                        pc += 1;
+                        let Some(source) = token_at(tokens, pc, state, output) else {
+                            pc = skip_block(tokens, pc);
+                            continue;
+                        };
+                        match variables.get(source) {
+                            Some(Value::Str(s)) => {
+                                // With two counters the first is the character's index, starting at 0.
+                                let chars = s
+                                    .chars()
+                                    .enumerate()
+                                    .map(|(index, c)| {
+                                        let c = Value::Str(c.to_string());
+                                        if with_index { vec![Value::Num(index as f64), c] } else { vec![c] }
+                                    })
+                                    .collect::<Vec<_>>();
+                                Box::new(chars.into_iter())
+                            }
+                            Some(_) => {
+                                state.report(output, format!("[ERROR: IncompatibleType]: PONDE can only iterate IN a KIRA: {}", source));
+                                pc = skip_block(tokens, pc);
+                                continue;
+                            }
+                            None => {
+                                state.report(output, format!("[ERROR: VanishValue]: Variable couldn't be found: {}", source));
+                                pc = skip_block(tokens, pc);
+                                continue;
+                            }
+                        }
+                    } else {
+                        let mut ranges = Vec::new();
+                        for offset in 0..var_names.len() {
+                            let Some(range) = token_at(tokens, pc + offset, state, output) else { break; };
+                            match parse_range(range) {
+                                Ok(bounds) => ranges.push(bounds),
+                                Err(e) => {
+                                    state.report(output, e);
+                                    break;
+                                }
+                            }
+                        }
+                        if ranges.len() != var_names.len() {
+                            pc = skip_block(tokens, pc);
                             continue;
                         }
+                        pc += ranges.len() - 1;
+                        range_product(&ranges)
                     };
 
                     pc += 1;
 
-                    if tokens.get(pc) != Some(&"{".to_string()) {
-                        output.push_str("[ERROR: Syntax]: Expected '{' to begin the loop\n");
+                    let mut stop_condition = None;
+                    if tokens.get(pc).map(String::as_str) == Some("MADE") {
+                        pc += 1;
+                        let end = tokens[pc..].iter().position(|t| t == "{").map_or(tokens.len(), |i| pc + i);
+                        stop_condition = Some(&tokens[pc..end]);
+                        pc = end;
+                    }
+
+                    let Some(open_brace) = token_at(tokens, pc, state, output) else {
+                        pc = skip_block(tokens, pc);
+                        continue;
+                    };
+                    if open_brace != "{" {
+                        state.report(output, "[ERROR: Syntax]: Expected '{' to begin the loop");
+                        pc = skip_block(tokens, pc);
                         continue;
                     }
                     pc += 1;
 
                     let loop_body_start = pc;
-                    let mut loop_body_end = pc;
+                    let Some(loop_body_end) = block_end(tokens, pc) else {
+                        state.report(output, "[ERROR: Syntax]: Could not find closing '}' for loop");
+                        pc = tokens.len();
+                        continue;
+                    };
+                    let body = &tokens[loop_body_start..loop_body_end];
+
+                    // Counters that reuse an existing variable's name get that variable back after the loop.
+                    let shadowed: Vec<(&str, Value)> = var_names
+                        .iter()
+                        .filter_map(|name| variables.get(*name).map(|value| (*name, value.clone())))
+                        .collect();
 
-                    while loop_body_end < tokens.len() {
-                        if tokens[loop_body_end] == "}" {
+                    let loop_started = Instant::now();
+                    let mut iterations = 0;
+                    for (iteration, values) in loop_values.enumerate() {
+                        if config.max_loop_iterations.is_some_and(|max| iteration >= max) {
+                            state.report(output, format!("[ERROR: LoopLimit]: PONDE stopped after {} iterations", iteration));
                             break;
                         }
-                        loop_body_end += 1;
-                    }
+                        for (name, value) in var_names.iter().zip(values) {
+                            if variable_limit_reached(state, variables, name, config, output) {
+                                return;
+                            }
+                            assign(variables, &state.watched, name, value, output);
+                        }
 
-                    if loop_body_end == tokens.len() {
-                        output.push_str("[ERROR: Syntax]: Could not find closing '}' for loop\n");
-                        continue;
+                        if let Some(condition) = stop_condition {
+                            match block_condition("MADE", condition, variables, config) {
+                                Ok(true) => break,
+                                Ok(false) => {}
+                                Err(e) => {
+                                    state.report(output, e);
+                                    break;
+                                }
+                            }
+                        }
+
+                        iterations += 1;
+                        state.loop_labels.push(label.map(str::to_string));
+                        execute(body, variables, output, config, state);
+                        state.loop_labels.pop();
+                        if state.halted {
+                            return;
+                        }
+                        if let Some(target) = &state.breaking {
+                            if target.is_none() || *target == label.map(str::to_string) {
+                                state.breaking = None;
+                            }
+                            break;
+                        }
+                        if state.raised.is_some() {
+                            break;
+                        }
                     }
 
-                    for i in start as i64..(end as i64) + 1 {
-                        variables.insert(var_name.to_string(), Value::Num(i as f64));
-                        let mut inner_pc = loop_body_start;
-
-                        while inner_pc < loop_body_end {
-                            match tokens.get(inner_pc).map(String::as_str) {
-                                Some("BAU") if inner_pc + 1 < loop_body_end => {
-                                    inner_pc += 1;
-                                    let token = &tokens[inner_pc];
-                                    if token.starts_with('"') && token.ends_with('"') {
-                                        output.push_str(
-                                            &format!("{}\n", &token[1..token.len() - 1])
-                                        );
-                                    } else {
-                                        match variables.get(token) {
-                                            Some(Value::Str(s)) =>
-                                                output.push_str(&format!("{}\n", s)),
-                                            Some(Value::Bool(b)) =>
-                                                output.push_str(&format!("{}\n", b)),
-                                            Some(Value::Num(n)) =>
-                                                output.push_str(&format!("{}\n", n)),
-                                            None =>
-                                                output.push_str(
-                                                    &format!("[ERROR: VanishValue]: Variable couldn't be found in scope: {}\n", token)
-                                                ),
-                                        }
-                                    }
-                                    inner_pc += 1;
-                                }
-                                Some("WA") if inner_pc + 4 < loop_body_end => {
-                                    inner_pc += 1;
-                                    let var_type = &tokens[inner_pc];
-                                    inner_pc += 1;
-                                    let var_name = &tokens[inner_pc];
-                                    inner_pc += 1;
-
-                                    if tokens[inner_pc] != "=" {
-                                        output.push_str(
-                                            "[ERROR: Syntax]: Expected '=' after variable name\n"
-                                        );
-                                        break;
-                                    }
-                                    inner_pc += 1;
-
-                                    let value = match var_type.as_str() {
-                                        "MOE" => {
-                                            let var_value = &tokens[inner_pc];
-
-                                            if
-                                            var_value.starts_with('<') &&
-                                                var_value.ends_with('>')
-                                            {
-                                                let expr = &var_value[1..var_value.len() - 1];
-
-                                                let expr = expr.replace("counter", &i.to_string());
-                                                match evaluate_arithmetic(&expr, &variables) {
-                                                    Ok(n) => Value::Num(n),
-                                                    Err(e) => {
-                                                        output.push_str(&format!("{}\n", e));
-                                                        continue;
-                                                    }
-                                                }
-                                            } else {
-                                                match var_value.parse::<f64>() {
-                                                    Ok(n) => Value::Num(n),
-                                                    Err(_) => {
-                                                        match variables.get(var_value) {
-                                                            Some(Value::Num(n)) => Value::Num(*n),
-                                                            _ => {
-                                                                output.push_str(
-                                                                    "[ERROR: InvalidValue]: Invalid number/arithmetic expression\n"
-                                                                );
-                                                                continue;
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        "KIRA" => {
-                                            let var_value = &tokens[inner_pc];
-                                            if
-                                            var_value.starts_with('"') &&
-                                                var_value.ends_with('"')
-                                            {
-                                                Value::Str(
-                                                    var_value[1..var_value.len() - 1].to_string()
-                                                )
-                                            } else {
-                                                match variables.get(var_value) {
-                                                    Some(Value::Str(s)) => Value::Str(s.clone()),
-                                                    _ => {
-                                                        output.push_str(
-                                                            "[ERROR: IncompatibleType]: KIRA does not support a nonstring\n"
-                                                        );
-                                                        continue;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        "BAULEAN" => {
-                                            let var_value = &tokens[inner_pc];
-                                            match var_value.as_str() {
-                                                "FLUFFY" => Value::Bool(true),
-                                                "FUZZY" => Value::Bool(false),
-                                                _ => {
-                                                    match variables.get(var_value) {
-                                                        Some(Value::Bool(b)) => Value::Bool(*b),
-                                                        _ => {
-                                                            output.push_str(
-                                                                "[ERROR: IncompatibleType]: BAULEAN requires FLUFFY/FUZZY or boolean variable\n"
-                                                            );
-                                                            continue;
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        _ => {
-                                            output.push_str(
-                                                &format!("Unknown type: {}\n", var_type)
-                                            );
-                                            continue;
-                                        }
-                                    };
-
-                                    variables.insert(var_name.to_string(), value);
-                                    inner_pc += 1;
-                                }
-                                Some("CO") if inner_pc + 3 < loop_body_end => {
-                                    inner_pc += 1;
-                                    let var_name = &tokens[inner_pc];
-                                    inner_pc += 1;
-
-                                    if tokens[inner_pc] != "=" {
-                                        output.push_str(
-                                            "[ERROR: Syntax]: Expected '=' in a reassignment\n"
-                                        );
-                                        break;
-                                    }
-                                    inner_pc += 1;
-
-                                    let existing_type = match variables.get(var_name) {
-                                        Some(Value::Str(_)) => "KIRA",
-                                        Some(Value::Bool(_)) => "BAULEAN",
-                                        Some(Value::Num(_)) => "MOE",
-                                        None => {
-                                            output.push_str(
-                                                &format!("[ERROR: VanishValue]: Variable couldn't be found in scope: {}\n", var_name)
-                                            );
-                                            continue;
-                                        }
-                                    };
-
-                                    let value = match existing_type {
-                                        "MOE" => {
-                                            let var_value = &tokens[inner_pc];
-                                            if
-                                            var_value.starts_with('<') &&
-                                                var_value.ends_with('>')
-                                            {
-                                                let expr = &var_value[1..var_value.len() - 1];
-                                                let expr = expr.replace("counter", &i.to_string());
-                                                match evaluate_arithmetic(&expr, &variables) {
-                                                    Ok(n) => Value::Num(n),
-                                                    Err(e) => {
-                                                        output.push_str(&format!("{}\n", e));
-                                                        continue;
-                                                    }
-                                                }
-                                            } else {
-                                                match var_value.parse::<f64>() {
-                                                    Ok(n) => Value::Num(n),
-                                                    Err(_) => {
-                                                        match variables.get(var_value) {
-                                                            Some(Value::Num(n)) => Value::Num(*n),
-                                                            _ => {
-                                                                output.push_str(
-                                                                    "[ERROR: IncompatibleType]: CO requires matching type (MOE)\n"
-                                                                );
-                                                                continue;
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        _ => {
-                                            continue;
-                                        }
-                                    };
-
-                                    variables.insert(var_name.to_string(), value);
-                                    inner_pc += 1;
-                                }
-                                _ => {
-                                    inner_pc += 1;
-                                }
+                    if config.profile {
+                        match state.loops.iter_mut().find(|profile| profile.at == loop_token) {
+                            Some(profile) => {
+                                profile.iterations += iterations;
+                                profile.elapsed += loop_started.elapsed();
                             }
+                            None => state.loops.push(LoopProfile {
+                                at: loop_token,
+                                label: format!("{} {}", keyword, counters),
+                                iterations,
+                                elapsed: loop_started.elapsed(),
+                            }),
                         }
                     }
 
+                    for (name, value) in shadowed {
+                        assign(variables, &state.watched, name, value, output);
+                    }
+                    // A BAUGH for a loop further out, or an error TAMESU caught, carries on outwards.
+                    if state.raised.is_some() || state.breaking.is_some() {
+                        return;
+                    }
                     pc = loop_body_end + 1;
                 } else {
-                    while pc < tokens.len() && tokens[pc] != "}" {
-                        pc += 1;
+                    pc = skip_block(tokens, pc);
+                }
+            }
+
+            Some("BAUIF") => {
+                pc += 1;
+                let Some(brace) = tokens[pc..].iter().position(|t| t == "{").map(|i| pc + i) else {
+                    state.report(output, "[ERROR: Syntax]: Expected '{' after BAUIF");
+                    return;
+                };
+                let Some(end) = block_end(tokens, brace + 1) else {
+                    state.report(output, "[ERROR: Syntax]: Could not find closing '}' for conditional");
+                    return;
+                };
+                let condition = &tokens[pc..brace];
+                pc = brace + 1;
+
+                // A condition with an error runs neither branch, and inside a block that isn't run
+                // the condition isn't even evaluated.
+                let (runs, else_runs) = if should_execute {
+                    match block_condition("BAUIF", condition, variables, config) {
+                        Ok(result) => (result, !result),
+                        Err(e) => {
+                            state.report(output, e);
+                            (false, false)
+                        }
+                    }
+                } else {
+                    (false, false)
+                };
+                condition_stack.push(Conditional { runs, else_runs: Some(else_runs), end });
+            }
+
+            Some("BAUELSE") => {
+                pc += 1;
+                let Some(runs) = else_after_if else {
+                    if should_execute {
+                        state.report(output, "[ERROR: Syntax]: BAUELSE without matching BAUIF");
                     }
+                    let _ = block_at(tokens, &mut pc, "BAUELSE", state, output);
+                    continue;
+                };
+                if tokens.get(pc).map(String::as_str) != Some("{") {
+                    state.report(output, "[ERROR: Syntax]: Expected '{' after BAUELSE");
+                    continue;
+                }
+                let Some(end) = block_end(tokens, pc + 1) else {
+                    state.report(output, "[ERROR: Syntax]: Could not find closing '}' for conditional");
+                    return;
+                };
+                pc += 1;
+                condition_stack.push(Conditional { runs, else_runs: None, end });
+            }
+
+            // Only the `}` that closes the innermost BAUIF or BAUELSE ends it.
+            Some("}") => {
+                if condition_stack.last().is_some_and(|block| block.end == pc) {
+                    else_runs = condition_stack.pop().and_then(|block| block.else_runs);
+                }
+                pc += 1;
+            }
+
+            Some("BAUGH") => {
+                pc += 1;
+                let label = tokens.get(pc).filter(|t| check_var_name(t).is_ok());
+                if label.is_some() {
                     pc += 1;
                 }
+                if !should_execute {
+                    continue;
+                }
+                if state.loop_labels.is_empty() {
+                    state.report(output, "[ERROR: Syntax]: BAUGH can only be used inside a PONDE loop");
+                } else if label.is_some_and(|label| !state.loop_labels.contains(&Some(label.clone()))) {
+                    state.report(output, format!(
+                        "[ERROR: UnknownLabel]: No PONDE loop named '{}' around this BAUGH",
+                        label.map_or("", String::as_str)
+                    ));
+                } else {
+                    state.breaking = Some(label.cloned());
+                    return;
+                }
             }
 
             _ => {
@@ -1,20 +1,66 @@
-mod interpreter;
-
-use druid::{AppLauncher, Data, Lens, Widget, WidgetExt, WindowDesc, Color};
+use druid::text::RichText;
+use druid::widget::Controller;
+use druid::{
+    lens, AppLauncher, Data, Env, Event, EventCtx, Lens, Widget, WidgetExt, WidgetId, WindowDesc, Color,
+};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use interpreter::{run_interpreter};
-use crate::interpreter::Value;
+use std::sync::Arc;
+use baux2::interpreter::{
+    run_interpreter_with_config, InterpreterConfig, Value, KEYWORD_ALIASES, RESERVED_WORDS,
+};
+
+mod diff;
+mod editor;
+
+use editor::{
+    BraceHighlight, CompletionController, EditorController, INSERT_COMPLETION, RUN_CODE, RUN_SELECTION,
+};
 
 #[derive(Clone, Data, Lens)]
 struct AppState {
     code: String,
     output: String,
+    completions: Arc<Vec<String>>,
+    /// Outputs of recent runs, oldest first.
+    history: Arc<Vec<String>>,
+    /// Which entry of `history` the output box is showing.
+    history_index: usize,
+    /// Show the output as a diff against the run before it.
+    show_diff: bool,
 }
 
+/// How many past outputs are kept for the back/forward buttons.
+const MAX_HISTORY: usize = 20;
+
+/// How much output a run can print before it is stopped, so the output box stays responsive.
+const MAX_OUTPUT: usize = 100_000;
+
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("--version") => {
+            println!("BAUx2 {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Some("--keywords") => {
+            for word in RESERVED_WORDS {
+                println!("{}", word);
+            }
+            for (alias, keyword) in KEYWORD_ALIASES {
+                println!("{} (same as {})", alias, keyword);
+            }
+            return;
+        }
+        _ => {}
+    }
+
     let initial_state = AppState {
         code: String::new(),
         output: String::new(),
+        completions: Arc::new(Vec::new()),
+        history: Arc::new(Vec::new()),
+        history_index: 0,
+        show_diff: false,
     };
     let main_window = WindowDesc::new(build_ui())
         .title("BAUDOL: The official BAUx2 IDE")
@@ -25,21 +71,107 @@ fn main() {
         .expect("bau bau... couldn't launch :(");
 }
 
+fn run(data: &mut AppState, code: &str) {
+    let mut variables: HashMap<String, Value> = HashMap::new();
+
+    data.output.clear();
+    let config = InterpreterConfig { max_output: Some(MAX_OUTPUT), ..InterpreterConfig::default() };
+    run_interpreter_with_config(code, &mut variables, &mut data.output, &config);
+
+    let history = Arc::make_mut(&mut data.history);
+    history.push(data.output.clone());
+    if history.len() > MAX_HISTORY {
+        history.remove(0);
+    }
+    data.history_index = history.len() - 1;
+}
+
+fn show_history(data: &mut AppState, index: usize) {
+    if let Some(output) = data.history.get(index) {
+        data.output = output.clone();
+        data.history_index = index;
+    }
+}
+
+/// Runs the code sent up by the editor for "Run Selection".
+struct RunController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for RunController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.is(RUN_CODE) => {
+                let code = cmd.get_unchecked(RUN_CODE).clone();
+                run(data, &code);
+                ctx.set_handled();
+            }
+            _ => child.event(ctx, event, data, env),
+        }
+    }
+}
+
+/// A worked out diff, with the `history_index` and `history` it was worked out for.
+type CachedDiff = (usize, Arc<Vec<String>>, RichText);
+
+/// The shown output compared with the output of the run before it. The diff is kept until
+/// another run is shown or the history changes, so editing the code doesn't work it out again.
+fn output_diff() -> impl Fn(&AppState) -> RichText {
+    let cache: RefCell<Option<CachedDiff>> = RefCell::new(None);
+    move |data| {
+        let mut cache = cache.borrow_mut();
+        if let Some((index, history, diff)) = cache.as_ref() {
+            if *index == data.history_index && history.same(&data.history) {
+                return diff.clone();
+            }
+        }
+        let previous = data
+            .history_index
+            .checked_sub(1)
+            .and_then(|index| data.history.get(index))
+            .map_or("", String::as_str);
+        let diff = diff::render(&diff::diff_lines(previous, &data.output));
+        *cache = Some((data.history_index, data.history.clone(), diff.clone()));
+        diff
+    }
+}
+
 fn build_ui() -> impl Widget<AppState> {
-    use druid::widget::{Flex, TextBox, Button, Scroll};
+    use druid::widget::{Flex, TextBox, Button, Scroll, List, Label, Checkbox, Either, RawLabel};
 
     let primary_color = Color::rgb8(241, 166, 214);
     let secondary_color = Color::rgb8(145, 168, 209);
     let background_color = Color::rgb8(247, 202, 201);
 
-    let code_input = TextBox::multiline()
-        .with_placeholder("BAU \"Bau Bau World!\"")
+    let editor_id = WidgetId::next();
+
+    let code_textbox = TextBox::multiline().with_placeholder("BAU \"Bau Bau World!\"");
+
+    let code_input = BraceHighlight::new(code_textbox)
+        .controller(EditorController)
+        .with_id(editor_id)
         .lens(AppState::code)
-        .expand_width()
-        .height(380.0)
+        .expand_width();
+
+    let code_scroll = Scroll::new(code_input)
+        .vertical()
+        .content_must_fill(true)
+        .fix_height(380.0)
         .background(background_color)
         .padding(10.0);
 
+    // Clicking a suggestion (or pressing Tab) completes the word being typed.
+    let suggestions = List::new(move || {
+        Button::dynamic(|word: &String, _env| word.clone())
+            .on_click(move |ctx, word: &mut String, _env| {
+                ctx.submit_command(INSERT_COMPLETION.with(word.clone()).to(editor_id));
+            })
+            .background(secondary_color)
+    })
+    .horizontal()
+    .with_spacing(4.0)
+    .lens(AppState::completions)
+    .fix_height(30.0)
+    .padding((10.0, 0.0));
+
     let output_textbox = TextBox::multiline()
         .with_placeholder("Bau Bau World!")
         .lens(AppState::output)
@@ -51,25 +183,84 @@ fn build_ui() -> impl Widget<AppState> {
     let output_scroll = Scroll::new(output_textbox)
         .vertical();
 
+    let diff_view = Scroll::new(
+        RawLabel::new()
+            .lens(lens::Map::new(output_diff(), |_data: &mut AppState, _diff| {}))
+            .expand_width()
+            .padding(10.0),
+    )
+    .vertical()
+    .fix_height(240.0)
+    .background(secondary_color);
+
+    let output_view = Either::new(|data: &AppState, _env| data.show_diff, diff_view, output_scroll);
+
     let execute_button = Button::new("Run")
         .on_click(|_ctx, data: &mut AppState, _env| {
-            let mut variables: HashMap<String, Value> = HashMap::new();
-
-            data.output.clear();
-            run_interpreter(&data.code, &mut variables, &mut data.output);
+            let code = data.code.clone();
+            run(data, &code);
         })
         .padding(2.0)
         .background(primary_color)
         .fix_width(60.0)
-        .border(primary_color, 4.0)
+        .border(primary_color, 4.0);
+
+    // Runs only the highlighted code (or the cursor's line), also bound to Ctrl+Enter.
+    let selection_button = Button::new("Run Selection")
+        .on_click(move |ctx, _data: &mut AppState, _env| {
+            ctx.submit_command(RUN_SELECTION.to(editor_id));
+        })
+        .padding(2.0)
+        .background(primary_color)
+        .fix_width(120.0)
+        .border(primary_color, 4.0);
+
+    // Steps through the outputs of earlier runs.
+    let history_back = Button::new("<")
+        .on_click(|_ctx, data: &mut AppState, _env| {
+            show_history(data, data.history_index.saturating_sub(1));
+        })
+        .disabled_if(|data: &AppState, _env| data.history_index == 0);
+
+    let history_forward = Button::new(">")
+        .on_click(|_ctx, data: &mut AppState, _env| {
+            show_history(data, data.history_index + 1);
+        })
+        .disabled_if(|data: &AppState, _env| data.history_index + 1 >= data.history.len());
+
+    let history_label = Label::dynamic(|data: &AppState, _env| {
+        if data.history.is_empty() {
+            String::new()
+        } else {
+            format!("Run {} of {}", data.history_index + 1, data.history.len())
+        }
+    });
+
+    let history_bar = Flex::row()
+        .with_child(history_back)
+        .with_spacer(6.0)
+        .with_child(history_label)
+        .with_spacer(6.0)
+        .with_child(history_forward)
+        .with_spacer(20.0)
+        .with_child(Checkbox::new("Compare with previous run").lens(AppState::show_diff));
+
+    let buttons = Flex::row()
+        .with_child(execute_button)
+        .with_spacer(10.0)
+        .with_child(selection_button)
         .center();
 
     Flex::column()
-        .with_child(execute_button)
+        .with_child(buttons)
         .with_spacer(20.0)
-        .with_child(code_input)
+        .with_child(code_scroll)
+        .with_child(suggestions)
         .with_spacer(20.0)
-        .with_child(output_scroll)
+        .with_child(history_bar)
+        .with_child(output_view)
         .padding(20.0)
         .background(background_color)
+        .controller(CompletionController)
+        .controller(RunController)
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+use baux2::analysis::analyze;
+use baux2::interpreter::tokenize;
+use std::collections::HashSet;
+
+fn names(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn statements_are_classified() {
+    let code = r#"
+WA MOE total = 0
+WA KIRA greeting = "hi {who}"
+PONDE i 1..5 MADE <total > cap> {
+  CO total = <total + i * step>
+}
+BAU greeting
+MIDASHI title
+"#;
+    let usage = analyze(&tokenize(code));
+
+    assert_eq!(usage.writes, names(&["total", "greeting", "i"]));
+    assert_eq!(usage.reads, names(&["who", "total", "cap", "i", "step", "greeting", "title"]));
+}
+
+#[test]
+fn keywords_numbers_and_comments_are_not_variables() {
+    let code = "WA BAULEAN ok = <FLUFFY AND LOG10 100 > 1.5> ; BAU hidden\nPONDE c IN word {\n}\n";
+    let usage = analyze(&tokenize(code));
+
+    assert_eq!(usage.writes, names(&["ok", "c"]));
+    assert_eq!(usage.reads, names(&["word"]));
+}
+
+#[test]
+fn wa_without_a_type_is_still_a_write() {
+    let usage = analyze(&tokenize("WA x = <y + 1>\nWA MOE z = x\n"));
+
+    assert_eq!(usage.writes, names(&["x", "z"]));
+    assert_eq!(usage.reads, names(&["y", "x"]));
+}
+
+#[test]
+fn chained_co_writes_every_target() {
+    let usage = analyze(&tokenize("WA MOE a = 0\nWA MOE b = 0\nCO a = b = <c + 1>\n"));
+
+    assert_eq!(usage.writes, names(&["a", "b"]));
+    assert_eq!(usage.reads, names(&["c"]));
+}
@@ -0,0 +1,117 @@
+use baux2::interpreter::{evaluate_arithmetic, run_to_string, Value};
+use proptest::prelude::*;
+use std::collections::HashMap;
+
+fn operator() -> impl Strategy<Value = &'static str> {
+    prop_oneof![Just("+"), Just("-"), Just("*"), Just("/"), Just("%"), Just("^")]
+}
+
+fn reference(left: f64, operator: &str, right: f64) -> f64 {
+    match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" => left / right,
+        "^" => left.powf(right),
+        _ => left % right,
+    }
+}
+
+fn same_result(actual: Result<f64, String>, expected: f64) -> bool {
+    match actual {
+        Ok(actual) => actual == expected,
+        Err(_) => !expected.is_finite(),
+    }
+}
+
+proptest! {
+    #[test]
+    fn literal_operands_match_reference(
+        left in -1.0e6f64..1.0e6,
+        op in operator(),
+        right in -1.0e6f64..1.0e6,
+    ) {
+        let variables = HashMap::new();
+        let expr = format!("{} {} {}", left, op, right);
+        let result = evaluate_arithmetic(&expr, &variables);
+        prop_assert!(same_result(result, reference(left, op, right)));
+    }
+
+    #[test]
+    fn variable_operands_match_reference(
+        left in -1000i64..1000,
+        op in operator(),
+        right in -1000i64..1000,
+    ) {
+        let mut variables = HashMap::new();
+        variables.insert("fuwawa".to_string(), Value::Num(left as f64));
+        variables.insert("mococo".to_string(), Value::Num(right as f64));
+        let result = evaluate_arithmetic(&format!("fuwawa {} mococo", op), &variables);
+        prop_assert!(same_result(result, reference(left as f64, op, right as f64)));
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics(expr in "\\PC*") {
+        let variables = HashMap::new();
+        let _ = evaluate_arithmetic(&expr, &variables);
+    }
+
+    #[test]
+    fn wrong_operand_count_is_an_error(numbers in prop::collection::vec(-100i64..100, 4..8)) {
+        let variables = HashMap::new();
+        let expr = numbers.iter().map(i64::to_string).collect::<Vec<_>>().join(" + ");
+        prop_assert!(evaluate_arithmetic(&expr, &variables).is_err());
+    }
+
+    #[test]
+    fn unknown_operator_is_an_error(
+        left in -100i64..100,
+        op in "[&|!?@#~]{1,2}",
+        right in -100i64..100,
+    ) {
+        let variables = HashMap::new();
+        let expr = format!("{} {} {}", left, op, right);
+        prop_assert!(evaluate_arithmetic(&expr, &variables).is_err());
+    }
+
+    #[test]
+    fn unknown_operand_is_an_error(name in "[a-z]{1,8}", op in operator()) {
+        prop_assume!(name.parse::<f64>().is_err());
+        let variables = HashMap::new();
+        let expr = format!("{} {} 1", name, op);
+        prop_assert!(evaluate_arithmetic(&expr, &variables).is_err());
+    }
+
+    #[test]
+    fn overflow_is_reported(left in 1.0e200f64..1.0e300, right in 1.0e200f64..1.0e300) {
+        let variables = HashMap::new();
+        let error = evaluate_arithmetic(&format!("{} * {}", left, right), &variables).unwrap_err();
+        prop_assert!(error.starts_with("[ERROR: NumericOverflow]"));
+    }
+
+    #[test]
+    fn division_by_zero_is_reported(left in -1000i64..1000) {
+        let variables = HashMap::new();
+        let error = evaluate_arithmetic(&format!("{} / 0", left), &variables).unwrap_err();
+        let kind = if left == 0 { "[ERROR: NotANumber]" } else { "[ERROR: NumericOverflow]" };
+        prop_assert!(error.starts_with(kind));
+    }
+}
+
+#[test]
+fn non_finite_literals_are_reported() {
+    let code = "WA MOE a = inf\nWA b = NaN\nWA MOE c = 1\nCO c = -infinity\nCO c = 1e400\n\
+                WA MOE d = <NaN + 1>\nBAU c\n";
+    let (output, errors) = run_to_string(code);
+    assert_eq!(
+        errors,
+        [
+            "[ERROR: NumericOverflow]: Result is too large for a MOE",
+            "[ERROR: NotANumber]: Result is not a number",
+            "[ERROR: NumericOverflow]: Result is too large for a MOE",
+            "[ERROR: NumericOverflow]: Result is too large for a MOE",
+            "[ERROR: NotANumber]: Result is not a number",
+        ]
+    );
+    assert!(output.ends_with("\n1\n"));
+}
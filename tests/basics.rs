@@ -0,0 +1,117 @@
+use baux2::interpreter::run_to_string;
+
+#[test]
+fn bau_prints_each_value_on_its_own_line() {
+    let (output, errors) = run_to_string("WA KIRA name = \"Mococo\"\nBAU name\nBAU \"hi {name}\"\n");
+    assert_eq!(output, "Mococo\nhi Mococo\n");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn arithmetic_assigns_numbers() {
+    let (output, _) = run_to_string("WA MOE x = <6 * 7>\nCO x = <x - 2>\nBAU x\n");
+    assert_eq!(output, "40\n");
+}
+
+#[test]
+fn ponde_counts_through_its_range() {
+    let (output, _) = run_to_string("PONDE i 1..3 {\n  BAU i\n}\n");
+    assert_eq!(output, "1\n2\n3\n");
+}
+
+#[test]
+fn errors_are_collected_and_the_program_keeps_going() {
+    let (output, errors) = run_to_string("BAU missing\nWA MOE x = \"text\"\nBAU \"still here\"\n");
+    assert_eq!(
+        errors,
+        [
+            "[ERROR: VanishValue]: Variable couldn't be found: missing",
+            "[ERROR: InvalidValue]: Invalid number/arithmetic expression",
+        ]
+    );
+    assert!(output.ends_with("still here\n"));
+}
+
+#[test]
+fn bauleans_work_bare_in_bau_and_made() {
+    let (output, errors) = run_to_string("BAU FLUFFY\nBAU FUZZY\n");
+    assert_eq!(output, "true\nfalse\n");
+    assert!(errors.is_empty());
+
+    let (output, _) = run_to_string("PONDE i 1..3 MADE FLUFFY {\n  BAU i\n}\nBAU \"done\"\n");
+    assert_eq!(output, "done\n");
+
+    let (output, _) = run_to_string("PONDE i 1..3 MADE FUZZY {\n  BAU i\n}\n");
+    assert_eq!(output, "1\n2\n3\n");
+
+    let (output, _) = run_to_string("WA BAULEAN flag = FUZZY\nPONDE i 1..2 MADE flag {\n  BAU i\n}\n");
+    assert_eq!(output, "1\n2\n");
+}
+
+#[test]
+fn chained_comparisons_check_every_pair() {
+    let code = "WA MOE x = 5\nWA BAULEAN inside = <0 < x < 10>\nWA BAULEAN above = <0 < x < 3>\n\
+                WA BAULEAN below = <6 < x < 10>\nBAU inside\nBAU above\nBAU below\n";
+    let (output, errors) = run_to_string(code);
+    assert_eq!(output, "true\nfalse\nfalse\n");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn a_space_before_the_closing_bracket_still_ends_the_expression() {
+    let (output, errors) = run_to_string("WA MOE a = 1\nWA MOE b = <a + 2 >\nWA BAULEAN c = <a > 0>\nBAU b\nBAU c\n");
+    assert_eq!(output, "3\ntrue\n");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn an_unterminated_expression_is_reported_before_running() {
+    let (output, errors) = run_to_string("BAU \"before\"\nWA MOE a = <1 + 2\nBAU \"after\"\n");
+    assert_eq!(errors, ["[ERROR: Syntax]: unterminated '<' expression starting on line 2"]);
+    assert!(!output.contains("before"));
+}
+
+#[test]
+fn statements_cut_off_at_the_end_are_incomplete() {
+    let truncated = [
+        "WA MOE",
+        "WA MOE x",
+        "WA MOE x =",
+        "WA MOE x = 1\nCO x",
+        "WA MOE x = 1\nCO x =",
+        "PONDE",
+        "PONDE i",
+        "MIDASHI",
+        "NAGERU",
+    ];
+    for code in truncated {
+        let (_, errors) = run_to_string(code);
+        assert_eq!(
+            errors,
+            ["[ERROR: IncompleteStatement]: Ran out of tokens in the middle of a statement"],
+            "{:?}",
+            code
+        );
+    }
+}
+
+#[test]
+fn trailing_comments_are_ignored() {
+    let code = "WA MOE x = 1; start\nCO x = <x + 1>; bump\nBAU x; show\n\
+                PONDE i 1..2 { ; loop\n  BAU i\n}; done\nBAU \"end\"\n";
+    let (output, errors) = run_to_string(code);
+    assert_eq!(output, "2\n1\n2\nend\n");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn only_raised_errors_are_returned() {
+    let code = "BAU \"[ERROR: x] just text\"\nTAMESU {\n  BAU missing\n} TSUKAMU {\n  BAU \"caught\"\n}\n\
+                BAU gone\n";
+    let (output, errors) = run_to_string(code);
+    assert_eq!(
+        output,
+        "[ERROR: x] just text\ncaught\n[ERROR: VanishValue]: Variable couldn't be found: gone\n"
+    );
+    assert_eq!(errors, ["[ERROR: VanishValue]: Variable couldn't be found: gone"]);
+}
@@ -0,0 +1,142 @@
+use baux2::interpreter::{run_interpreter, run_interpreter_with_config, InterpreterConfig, Value};
+use std::collections::HashMap;
+
+#[test]
+fn host_variables_are_visible_to_the_script() {
+    let mut variables = HashMap::new();
+    variables.insert("x".to_string(), Value::from(10.0));
+    variables.insert("name".to_string(), Value::from("Fuwawa"));
+    variables.insert("loud".to_string(), Value::from(true));
+
+    let code = "WA MOE doubled = <x * 2>\nBAU doubled\nBAU name\nBAU loud\n";
+    let mut output = String::new();
+    run_interpreter(code, &mut variables, &mut output);
+
+    assert_eq!(output, "20\nFuwawa\ntrue\n");
+    assert_eq!(variables.get("doubled"), Some(&Value::Num(20.0)));
+}
+
+#[test]
+fn decimal_places_apply_to_printed_numbers() {
+    let config = InterpreterConfig { decimal_places: Some(2), ..InterpreterConfig::default() };
+    let code = "WA MOE third = <1 / 3>\nBAU third\nWA MOE tiny = -0.001\nBAU \"{tiny} and {third}\"\nWA KIRA kept = \"{third}\"\nBAU kept\n";
+    let mut variables = HashMap::new();
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut variables, &mut output, &config);
+
+    assert_eq!(output, "0.33\n0.00 and 0.33\n0.3333333333333333\n");
+}
+
+#[test]
+fn zenyomu_reads_the_host_input() {
+    let code = "WA KIRA text = <ZENYOMU>\nBAU text\nWA BAULEAN empty = <text == \"\">\nBAU empty\n";
+    let config = InterpreterConfig { input: Some("line one\nline two".to_string()), ..InterpreterConfig::default() };
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+    assert_eq!(output, "line one\nline two\nfalse\n");
+
+    let mut output = String::new();
+    run_interpreter(code, &mut HashMap::new(), &mut output);
+    assert_eq!(output, "\ntrue\n");
+}
+
+#[test]
+fn profile_lists_each_loop() {
+    let config = InterpreterConfig { profile: true, ..InterpreterConfig::default() };
+    let code = "PONDE i 1..3 {\n}\nPONDE a,b 1..2 1..2 MADE <a == 2> {\n}\nBAU \"done\"\n";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[..3], ["done", "--- PROFILE ---", "loop                 iterations         time"]);
+    assert!(lines[3].starts_with("PONDE i") && lines[3].contains(" 3 ") && lines[3].ends_with(" ms"));
+    assert!(lines[4].starts_with("PONDE a,b") && lines[4].contains(" 2 "));
+    assert!(lines[5].starts_with("total"));
+    assert_eq!(lines[6], "deepest nesting: 1");
+    assert_eq!(lines.len(), 7);
+}
+
+#[test]
+fn profile_adds_up_each_run_of_a_nested_loop() {
+    let config = InterpreterConfig { profile: true, ..InterpreterConfig::default() };
+    let code = "PONDE i 1..3 {\nPONDE j 1..2 {\n}\n}\n";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+
+    let rows: Vec<&str> = output.lines().filter(|line| line.starts_with("PONDE")).collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].starts_with("PONDE j") && rows[0].contains(" 6 "));
+    assert!(rows[1].starts_with("PONDE i") && rows[1].contains(" 3 "));
+}
+
+#[test]
+fn profile_reports_the_deepest_nesting() {
+    let config = InterpreterConfig { profile: true, ..InterpreterConfig::default() };
+    let code = "PONDE i 1..2 {
+TAMESU {
+PONDE j 1..2 {
+}
+}
+}
+PONDE k 1..2 {
+}
+";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+
+    assert!(output.ends_with("deepest nesting: 3\n"));
+}
+
+#[test]
+fn verbose_runs_report_the_deepest_nesting() {
+    let config = InterpreterConfig { verbose: true, ..InterpreterConfig::default() };
+    let code = "PONDE i 1..2 {\nBAU i\n}\n";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+    assert_eq!(output, "1\n2\ndeepest nesting: 1\n");
+
+    let code = format!("CHIHUAHUA\n{}", code);
+    let mut output = String::new();
+    run_interpreter_with_config(&code, &mut HashMap::new(), &mut output, &InterpreterConfig::default());
+    assert_eq!(output, "1\n2\ndeepest nesting: 1\n");
+}
+
+#[test]
+fn tamesu_does_not_catch_the_step_limit() {
+    let config = InterpreterConfig { max_steps: Some(20), ..InterpreterConfig::default() };
+    let code = "TAMESU {\nPONDE i 1..100 {\nBAU i\n}\n} TSUKAMU {\nBAU \"caught\"\n}\nBAU \"the end\"\n";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+
+    assert!(output.contains("[ERROR: StepLimit]"));
+    assert!(!output.contains("caught"));
+    assert!(!output.contains("the end"));
+}
+
+#[test]
+fn max_output_truncates_and_stops() {
+    let config = InterpreterConfig { max_output: Some(10), ..InterpreterConfig::default() };
+    let code = "PONDE i 1..100 {\nBAU \"line {i}\"\n}\nWA MOE after = 1\n";
+    let mut variables = HashMap::new();
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut variables, &mut output, &config);
+
+    assert_eq!(output, "line 1\nlin\n[output truncated]\n");
+    assert!(!variables.contains_key("after"));
+
+    let code = "TAMESU {\nPONDE i 1..100 {\nBAU \"line {i}\"\n}\n}\n";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+    assert_eq!(output, "line 1\nlin\n[output truncated]\n");
+}
+
+#[test]
+fn epsilon_applies_to_less_or_equal_and_greater_or_equal() {
+    let config = InterpreterConfig { epsilon: 0.1, ..InterpreterConfig::default() };
+    let code = "WA BAULEAN a = <1.05 == 1>\nWA BAULEAN b = <1.05 <= 1>\nWA BAULEAN c = <1 >= 1.05>\n\
+                WA BAULEAN d = <1.05 < 1>\nBAU a\nBAU b\nBAU c\nBAU d\n";
+    let mut output = String::new();
+    run_interpreter_with_config(code, &mut HashMap::new(), &mut output, &config);
+
+    assert_eq!(output, "true\ntrue\ntrue\nfalse\n");
+}
@@ -0,0 +1,55 @@
+use baux2::interpreter::run_to_string;
+use std::fs;
+use std::path::Path;
+
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => diff.push_str(&format!("  {}\n", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    diff.push_str(&format!("- {}\n", e));
+                }
+                if let Some(a) = a {
+                    diff.push_str(&format!("+ {}\n", a));
+                }
+            }
+        }
+    }
+    diff
+}
+
+#[test]
+fn programs_match_expected_output() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut failures = Vec::new();
+    let mut checked = 0;
+
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("tests/programs should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "baux2"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let code = fs::read_to_string(&path).unwrap();
+        let expected_path = path.with_extension("expected");
+        let expected = fs::read_to_string(&expected_path)
+            .unwrap_or_else(|_| panic!("missing {}", expected_path.display()));
+
+        let (output, _) = run_to_string(&code);
+        checked += 1;
+
+        if output != expected {
+            failures.push(format!("{}:\n{}", path.display(), line_diff(&expected, &output)));
+        }
+    }
+
+    assert!(checked > 0, "no .baux2 programs found in {}", dir.display());
+    assert!(failures.is_empty(), "output changed for:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,82 @@
+use baux2::interpreter::{check_syntax, tokenize};
+
+#[test]
+fn tabs_and_spaces_tokenize_the_same() {
+    let spaces = "WA MOE x = <3 * 4>\nPONDE i 1..2 {\n    CO x = <x - i> ; comment\n  BAU x\n}\n";
+    let tabs = "WA\tMOE\tx\t=\t<3\t*\t4>\nPONDE\ti 1..2\t{\n\tCO x = <x\t-\ti>\t;\tcomment\n \t BAU x\n}\n";
+
+    assert_eq!(tokenize(tabs), tokenize(spaces));
+}
+
+#[test]
+fn tabs_inside_strings_are_kept() {
+    assert_eq!(tokenize("BAU \"a\tb\" ; \"c\td\""), vec!["BAU", "\"a\tb\""]);
+}
+
+#[test]
+fn check_syntax_points_at_the_unmatched_brace() {
+    let code = "BAU \"{fine}\" ; { in a comment\nPONDE i 1..2 {\n  BAU i\n";
+    let problems = check_syntax(code);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(&code[problems[0].range.clone()], "{");
+    assert_eq!(problems[0].range.start, code.rfind('{').unwrap());
+    assert!(problems[0].message.contains("line 2"));
+
+    assert!(check_syntax("PONDE i 1..2 {\n  BAU i\n}\n").is_empty());
+}
+
+#[test]
+fn spaced_less_than_is_a_comparison_not_an_expression() {
+    assert_eq!(tokenize("BAUIF x < 5 {\n}"), ["BAUIF", "x", "<", "5", "{", "}"]);
+    assert_eq!(tokenize("BAUIF x <= 5 {"), ["BAUIF", "x", "<", "=", "5", "{"]);
+    assert_eq!(tokenize("WA BAULEAN b = <x < 5>"), ["WA", "BAULEAN", "b", "=", "<x < 5>"]);
+    assert!(check_syntax("BAUIF x < 5 {\n  BAU x\n}\n").is_empty());
+}
+
+#[test]
+fn spaced_less_than_outside_a_condition_starts_an_expression() {
+    assert_eq!(tokenize("WA MOE b = < 1 + 2>"), ["WA", "MOE", "b", "=", "<1 + 2>"]);
+    assert!(check_syntax("WA MOE b = < 1 + 2>\nBAU b\n").is_empty());
+    assert_eq!(tokenize("PONDE i 0..9 MADE i > 3 {"), ["PONDE", "i", "0..9", "MADE", "i", ">", "3", "{"]);
+}
+
+#[test]
+fn trailing_comments_end_the_last_token_of_their_line() {
+    assert_eq!(tokenize("BAU \"hi\"; greeting\nBAU x"), ["BAU", "\"hi\"", "BAU", "x"]);
+    assert_eq!(tokenize("CO x = 5; reset\nBAU x"), ["CO", "x", "=", "5", "BAU", "x"]);
+    assert_eq!(
+        tokenize("PONDE i 1..2 {; loop\n  BAU i ; each\n}; done\nBAU i"),
+        ["PONDE", "i", "1..2", "{", "BAU", "i", "}", "BAU", "i"]
+    );
+    assert_eq!(tokenize("BAU \"a; b\" ; not a string"), ["BAU", "\"a; b\""]);
+}
+
+#[test]
+fn check_syntax_points_at_bad_assignments() {
+    let underlined = |code: &str| -> Vec<(String, String)> {
+        check_syntax(code)
+            .into_iter()
+            .map(|problem| (code[problem.range].to_string(), problem.message))
+            .collect()
+    };
+
+    assert_eq!(
+        underlined("WA MOE PONDE = 1\nCO x = 9x = 2\n"),
+        [
+            ("PONDE".to_string(), "[ERROR: ReservedName]: 'PONDE' is a reserved keyword".to_string()),
+            ("9x".to_string(), "[ERROR: Syntax]: invalid variable name '9x'".to_string()),
+        ]
+    );
+    assert_eq!(
+        underlined("WA MOE x 5\nCO x 5\n"),
+        [
+            ("5".to_string(), "[ERROR: Syntax]: Expected '=' after variable name".to_string()),
+            ("5".to_string(), "[ERROR: Syntax]: Expected '=' in a reassignment".to_string()),
+        ]
+    );
+    assert_eq!(
+        underlined("WA NUMBER x = 5\n"),
+        [("NUMBER".to_string(), "[ERROR: Syntax]: Unknown type: NUMBER".to_string())]
+    );
+    assert!(underlined("WA x = <1 + 2>\nWA KIRA s = \"WA MOE = \"\nCO x = <x * 2>\nWA MOE y").is_empty());
+}